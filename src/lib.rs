@@ -0,0 +1,7452 @@
+use std::fmt;
+use std::io;
+
+use bigint::BigInt;
+use eval::{Context, Settings};
+use parser::Parser;
+
+/// What is wrong with an input or could not be computed. Kinds with
+/// many causes carry the message shown for them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CalcError {
+    /// a token where none of its kind can be, like the `*` of `1 + * 2`
+    UnexpectedToken(&'static str),
+    /// a bracket that is not closed, or closed without being opened
+    UnmatchedParen,
+    /// an absolute value `|x` that is not closed
+    UnmatchedBar,
+    /// a malformed number, date or time
+    InvalidLiteral(&'static str),
+    UnknownIdentifier,
+    UnknownFunction,
+    UnknownUnit,
+    NotAFunction,
+    TooFewArguments,
+    TooManyArguments,
+    /// arguments a function cannot take, like `map(1, 2)`
+    InvalidArguments(&'static str),
+    /// a value of the wrong kind, like adding a date and a length
+    TypeMismatch(&'static str),
+    DivisionByZero,
+    /// an argument where the function is not defined, like `ln(0)`
+    Domain(&'static str),
+    /// a result or input exceeding what can be computed
+    Overflow(&'static str),
+    /// an equation or rate of return without a single solution
+    NoSolution(&'static str),
+    /// a definition that is not allowed, like assigning to `pi`
+    InvalidDefinition(&'static str),
+    NothingToEvaluate,
+}
+
+impl fmt::Display for CalcError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let message = match self {
+            CalcError::UnmatchedParen => "unmatched bracket",
+            CalcError::UnmatchedBar => "unmatched '|'",
+            CalcError::UnknownIdentifier => "unknown identifier",
+            CalcError::UnknownFunction => "unknown function",
+            CalcError::UnknownUnit => "unknown unit",
+            CalcError::NotAFunction => "not a function",
+            CalcError::TooFewArguments => "too few arguments",
+            CalcError::TooManyArguments => "too many arguments",
+            CalcError::DivisionByZero => "division by zero",
+            CalcError::NothingToEvaluate => "nothing to evaluate",
+            CalcError::UnexpectedToken(message)
+            | CalcError::InvalidLiteral(message)
+            | CalcError::InvalidArguments(message)
+            | CalcError::TypeMismatch(message)
+            | CalcError::Domain(message)
+            | CalcError::Overflow(message)
+            | CalcError::NoSolution(message)
+            | CalcError::InvalidDefinition(message) => message,
+        };
+        write!(f, "{}", message)
+    }
+}
+
+impl std::error::Error for CalcError {}
+
+/// The byte offsets a token or an error covers in the input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// A token or another piece of the input along with where it is found.
+#[derive(Debug, Clone)]
+pub struct Spanned<T> {
+    pub node: T,
+    pub span: Span,
+}
+
+/// An error in the input along with where it is found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub error: CalcError,
+    pub span: Span,
+}
+
+impl Diagnostic {
+    /// The line of `input` holding the error with a caret below its place
+    /// and the message after it, like
+    ///
+    /// ```text
+    /// 1 + * 2
+    ///     ^ invalid operator
+    /// ```
+    pub fn render(&self, input: &str) -> String {
+        let start = self.span.start.min(input.len());
+        let line_start = input[..start].rfind('\n').map_or(0, |newline| newline + 1);
+        let line_end = input[start..].find('\n').map_or(input.len(), |newline| start + newline);
+        let line = &input[line_start..line_end];
+        let column = input[line_start..start].chars().count();
+        let width = input.get(start..self.span.end.min(line_end)).map_or(0, |text| text.chars().count()).max(1);
+        format!("{}\n{}{} {}", line, " ".repeat(column), "^".repeat(width), self.error)
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.error, f)
+    }
+}
+
+impl std::error::Error for Diagnostic {}
+
+impl From<Diagnostic> for CalcError {
+    fn from(diagnostic: Diagnostic) -> Self {
+        diagnostic.error
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Token {
+    /// a number literal in decimal, as written without digit separators or
+    /// converted from another base, so that it can be read exactly
+    Literal(String),
+    Ident(String),
+    Operator(&'static str),
+    /// a `%` that is not followed by an operand, hence a percent sign
+    /// rather than the remainder operator
+    Percent,
+    /// a run of superscript digits such as the `²` of `x²`
+    Superscript(f64),
+    /// a date like `2024-03-01`
+    Date(time::Date),
+    /// a clock time like `18:30` or `7:45:10`, in seconds
+    Time(f64),
+    Invalid(CalcError),
+    Empty,
+    End,
+}
+
+pub struct Lexer<'a> {
+    input: &'a str,
+    /// the offset of the rest of the input, which is yet to be scanned
+    pos: usize,
+    /// registered operators, scanned like the builtin ones
+    symbols: Vec<&'static str>,
+}
+
+/// A source of tokens for the parser, which gives `Token::End` once
+/// there are no more.
+pub trait Scan {
+    fn next_token(&mut self) -> Spanned<Token>;
+}
+
+/// An invalid token and where it is found.
+pub type LexError = Diagnostic;
+
+/// Any iterator over tokens, like one made up of tokens of another
+/// front end, as a source for the parser.
+pub struct Tokens<I> {
+    tokens: I,
+    /// where the last token ends, and so the input
+    end: usize,
+}
+
+impl<I> Tokens<I> {
+    pub fn new(tokens: I) -> Self {
+        Tokens { tokens, end: 0 }
+    }
+}
+
+impl<I: Iterator<Item = Result<Spanned<Token>, LexError>>> Scan for Tokens<I> {
+    fn next_token(&mut self) -> Spanned<Token> {
+        let token = match self.tokens.next() {
+            Some(Ok(token)) => token,
+            Some(Err(error)) => Spanned { node: Token::Invalid(error.error), span: error.span },
+            None => Spanned { node: Token::End, span: Span { start: self.end, end: self.end } },
+        };
+        self.end = token.span.end;
+        token
+    }
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(s: &'a str) -> Self {
+        Lexer {
+            input: s,
+            pos: 0,
+            symbols: Vec::new(),
+        }
+    }
+
+    /// A lexer that also scans the operators registered in `operators`.
+    pub fn with_operators(s: &'a str, operators: &operators::Operators) -> Self {
+        Lexer {
+            symbols: operators.symbols().collect(),
+            ..Lexer::new(s)
+        }
+    }
+
+    /// Moves on to `rest`, the part of the input after a token.
+    fn advance(&mut self, rest: &'a str) {
+        self.pos = self.input.len() - rest.len();
+    }
+
+    fn is_word_operator(&self, s: &str) -> bool {
+        let len = s.find(|c: char| ! (c.is_alphanumeric() || c == '_')).unwrap_or(s.len());
+        self.symbols.contains(&&s[..len])
+    }
+}
+
+// longer operators come first so that `<=` is not scanned as `<`
+const OPERATORS: [&str; 36] = [
+    "//=", "+=", "-=", "*=", "/=", "^=", "..",
+    "<=", ">=", "==", "!=", "&&", "||", "<<", ">>", "->", "//",
+    "+", "-", "*", "/", "^", "!", "<", ">", "&", "|", "?", ":", "=", "(", ")", "[", "]", ",", ";",
+];
+
+// other spellings of operators, as found in pasted text; `√` is a prefix
+// square root
+const ALIASES: [(&str, &str); 8] = [
+    ("×", "*"), ("·", "*"), ("÷", "/"), ("−", "-"),
+    ("≤", "<="), ("≥", ">="), ("≠", "!="), ("√", "√"),
+];
+
+// operators and other reserved words
+const KEYWORDS: [&str; 6] = ["xor", "let", "in", "to", "of", "step"];
+
+fn starts_with_keyword(s: &str) -> bool {
+    let len = s.find(|c: char| ! (c.is_alphanumeric() || c == '_')).unwrap_or(s.len());
+    KEYWORDS.contains(&&s[..len])
+}
+
+/// Whether `symbol` is spelled like a builtin operator or keyword, which
+/// registered operators cannot be.
+fn is_reserved(symbol: &str) -> bool {
+    OPERATORS.contains(&symbol) || KEYWORDS.contains(&symbol) || ALIASES.iter().any(|(spelling, _)| *spelling == symbol)
+        || ["**", "**=", "%", "%="].contains(&symbol)
+}
+
+// digit runs may contain `_` separators, which are validated by
+// `separators_ok` once the whole literal has been scanned
+fn digits_len(s: &str) -> usize {
+    s.find(|c: char| ! c.is_ascii_digit() && c != '_').unwrap_or(s.len())
+}
+
+fn separators_ok(literal: &str, is_digit: fn(&u8) -> bool) -> bool {
+    let bytes = literal.as_bytes();
+    bytes.iter().enumerate().all(|(i, b)| {
+        *b != b'_' || (i > 0 && i + 1 < bytes.len() && is_digit(&bytes[i - 1]) && is_digit(&bytes[i + 1]))
+    })
+}
+
+fn superscript_digit(c: char) -> Option<u32> {
+    match c {
+        '⁰' => Some(0),
+        '¹' => Some(1),
+        '²' => Some(2),
+        '³' => Some(3),
+        '⁴'..='⁹' => Some(c as u32 - '⁴' as u32 + 4),
+        _ => None,
+    }
+}
+
+// the number written by the first `len` characters of `s`, if they are
+// all ASCII digits
+fn digits_at(s: &str, len: usize) -> Option<i64> {
+    s.get(..len).filter(|digits| digits.bytes().all(|b| b.is_ascii_digit())).map(|digits| digits.parse().unwrap())
+}
+
+// A date `yyyy-mm-dd` or a time `h:mm` or `h:mm:ss` at the start of `s`,
+// with its length. Anything else, like `2024-3-1`, is left to the
+// arithmetic it reads as.
+fn temporal_literal(s: &str) -> Option<(Token, usize)> {
+    let ends = |len: usize| ! s[len..].starts_with(|c: char| c.is_alphanumeric() || matches!(c, '.' | '_' | ':'));
+    if s.get(4..5) == Some("-") && s.get(7..8) == Some("-") {
+        if let (Some(year), Some(month), Some(day)) = (digits_at(s, 4), digits_at(&s[5..], 2), digits_at(&s[8..], 2)) {
+            if ends(10) {
+                let date = time::Date::from_ymd(year, month, day);
+                return Some((date.map_or(Token::Invalid(CalcError::InvalidLiteral("no such date")), Token::Date), 10));
+            }
+        }
+    }
+    let hour_len = s.find(|c: char| ! c.is_ascii_digit()).unwrap_or(s.len());
+    if ! (1..=2).contains(&hour_len) || ! s[hour_len..].starts_with(':') {
+        return None;
+    }
+    let hours = digits_at(s, hour_len)?;
+    let minutes = digits_at(&s[hour_len + 1..], 2)?;
+    let (seconds, len) = match s[hour_len + 3..].strip_prefix(':') {
+        Some(rest) => (digits_at(rest, 2)?, hour_len + 6),
+        None => (0, hour_len + 3),
+    };
+    if minutes >= 60 || seconds >= 60 || ! ends(len) {
+        return None;
+    }
+    Some((Token::Time((hours * 3600 + minutes * 60 + seconds) as f64), len))
+}
+
+/// A literal in any base from 2 to 36 like `36#z` or `2#1011`; a `#`
+/// only starts a comment after digits when something other than a digit
+/// or letter follows.
+fn radix_literal(s: &str) -> Option<(Token, usize)> {
+    let base_len = s.find(|c: char| ! c.is_ascii_digit()).unwrap_or(s.len());
+    let rest = s[base_len..].strip_prefix('#')?;
+    if ! rest.starts_with(|c: char| c.is_ascii_alphanumeric()) {
+        return None;
+    }
+    let len = rest.find(|c: char| ! c.is_ascii_alphanumeric() && c != '_').unwrap_or(rest.len());
+    let token = match s[..base_len].parse() {
+        Ok(_) if ! separators_ok(&rest[..len], u8::is_ascii_alphanumeric) => Token::Invalid(CalcError::InvalidLiteral("misplaced digit separator")),
+        Ok(radix @ 2..=36) => match parse_radix(&rest[..len], radix) {
+            Some(number) => Token::Literal(number.to_string()),
+            None => Token::Invalid(CalcError::InvalidLiteral("invalid digit for the literal's base")),
+        },
+        _ => Token::Invalid(CalcError::InvalidLiteral("base of a literal must be from 2 to 36")),
+    };
+    Some((token, base_len + 1 + len))
+}
+
+fn parse_radix(digits: &str, radix: u32) -> Option<BigInt> {
+    let base = BigInt::from(radix as i64);
+    digits.chars().filter(|c| *c != '_').try_fold(BigInt::from(0), |number, c| {
+        c.to_digit(radix).map(|digit| &(&number * &base) + &BigInt::from(digit as i64))
+    })
+}
+
+// `s` without the whitespace and comments it starts with
+fn skip_blanks(s: &str) -> &str {
+    let mut s = s.trim_start();
+    // a comment runs to the end of its line
+    while s.starts_with('#') {
+        s = s.find('\n').map_or("", |end| s[end..].trim_start());
+    }
+    s
+}
+
+impl Scan for Lexer<'_> {
+    fn next_token(&mut self) -> Spanned<Token> {
+        let rest = skip_blanks(&self.input[self.pos..]);
+        let start = self.input.len() - rest.len();
+        let token = self.scan_token();
+        let mut end = self.pos;
+        // scanning goes on past the first character of an invalid token,
+        // which is its span
+        if end <= start {
+            end = start + rest.chars().next().map_or(0, char::len_utf8);
+            self.pos = end;
+        }
+        Spanned { node: token, span: Span { start, end } }
+    }
+}
+
+/// The tokens up to the end of the input with where they are found, and
+/// the invalid ones as errors.
+impl Iterator for Lexer<'_> {
+    type Item = Result<Spanned<Token>, LexError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let token = self.next_token();
+        match token.node {
+            Token::End => None,
+            Token::Invalid(error) => Some(Err(LexError { error, span: token.span })),
+            _ => Some(Ok(token)),
+        }
+    }
+}
+
+impl Lexer<'_> {
+    fn scan_token(&mut self) -> Token {
+        let s = skip_blanks(&self.input[self.pos..]);
+        let first = match s.chars().next() {
+            Some(ch) => ch,
+            None => return Token::End,
+        };
+
+        match first {
+            '0' if s[1..].starts_with(['x', 'X', 'o', 'O', 'b', 'B']) => {
+                let radix = match &s[1..2] {
+                    "x" | "X" => 16,
+                    "o" | "O" => 8,
+                    _ => 2,
+                };
+                let rest = &s[2..];
+                let len = rest.find(|c: char| ! c.is_ascii_alphanumeric() && c != '_').unwrap_or(rest.len());
+                if len == 0 {
+                    return Token::Invalid(CalcError::InvalidLiteral("missing digits after radix prefix"));
+                }
+                if ! separators_ok(&rest[..len], u8::is_ascii_alphanumeric) {
+                    return Token::Invalid(CalcError::InvalidLiteral("misplaced digit separator"));
+                }
+                let number = match parse_radix(&rest[..len], radix) {
+                    Some(number) => number,
+                    None => return Token::Invalid(CalcError::InvalidLiteral("invalid digit for the literal's base")),
+                };
+                self.advance(&rest[len..]);
+                Token::Literal(number.to_string())
+            },
+
+            ch if ch.is_ascii_digit() && radix_literal(s).is_some() => {
+                let (token, len) = radix_literal(s).unwrap();
+                self.advance(&s[len..]);
+                token
+            },
+
+            ch if ch.is_ascii_digit() && temporal_literal(s).is_some() => {
+                let (token, len) = temporal_literal(s).unwrap();
+                self.advance(&s[len..]);
+                token
+            },
+
+            ch if ch.is_ascii_digit() || (ch == '.' && s[1..].starts_with(|c: char| c.is_ascii_digit())) => {
+                let mut idx = digits_len(s);
+                // `1..10` is a range, not `1.` followed by `.10`
+                if s[idx..].starts_with('.') && ! s[idx..].starts_with("..") {
+                    idx += 1 + digits_len(&s[idx + 1..]);
+                }
+                if s[idx..].starts_with(['e', 'E']) {
+                    let mut end = idx + 1;
+                    if s[end..].starts_with(['+', '-']) {
+                        end += 1;
+                    }
+                    let exponent = digits_len(&s[end..]);
+                    if exponent == 0 {
+                        return Token::Invalid(CalcError::InvalidLiteral("missing digits in exponent"));
+                    }
+                    idx = end + exponent;
+                }
+                if ! separators_ok(&s[..idx], u8::is_ascii_digit) {
+                    return Token::Invalid(CalcError::InvalidLiteral("misplaced digit separator"));
+                }
+                let literal = s[..idx].replace('_', "");
+                self.advance(&s[idx..]);
+                Token::Literal(literal)
+            },
+
+            _ if s.starts_with("**=") => {
+                self.advance(&s[3..]);
+                Token::Operator("^=")
+            },
+
+            _ if s.starts_with("**") => {
+                self.advance(&s[2..]);
+                Token::Operator("^")
+            },
+
+            _ if s.starts_with("%=") => {
+                self.advance(&s[2..]);
+                Token::Operator("%=")
+            },
+
+            '%' => {
+                self.advance(&s[1..]);
+                let rest = s[1..].trim_start();
+                // a keyword is no operand, like in `20% of 150`
+                if rest.starts_with(|c: char| c.is_alphanumeric() || matches!(c, '.' | '_' | '('))
+                    && ! starts_with_keyword(rest) && ! self.is_word_operator(rest) {
+                    Token::Operator("%")
+                } else {
+                    Token::Percent
+                }
+            },
+
+            'π' => {
+                self.advance(&s['π'.len_utf8()..]);
+                Token::Ident("pi".to_string())
+            },
+
+            // currency symbols before amounts are ignored
+            ch @ ('$' | '€' | '£' | '¥') if s[ch.len_utf8()..].starts_with(|c: char| c.is_ascii_digit() || c == '.') => {
+                self.advance(&s[ch.len_utf8()..]);
+                self.scan_token()
+            },
+
+            ch if superscript_digit(ch).is_some() || ch == '⁻' => {
+                let (sign, digits) = match s.strip_prefix('⁻') {
+                    Some(digits) => (-1.0, digits),
+                    None => (1.0, s),
+                };
+                let len = digits.find(|c: char| superscript_digit(c).is_none()).unwrap_or(digits.len());
+                if len == 0 {
+                    return Token::Invalid(CalcError::InvalidLiteral("missing digits in superscript"));
+                }
+                let exponent = digits[..len].chars()
+                    .fold(0.0, |number, c| number * 10.0 + superscript_digit(c).unwrap() as f64);
+                self.advance(&digits[len..]);
+                Token::Superscript(sign * exponent)
+            },
+
+            ch if ch.is_alphabetic() || ch == '_' => {
+                // `²` counts as alphanumeric, but `x²` is a power of `x`
+                let len = s.find(|c: char| ! (c.is_alphanumeric() || c == '_') || superscript_digit(c).is_some())
+                    .unwrap_or(s.len());
+                self.advance(&s[len..]);
+                match KEYWORDS.iter().chain(&self.symbols).find(|keyword| **keyword == &s[..len]) {
+                    Some(keyword) => Token::Operator(keyword),
+                    None => Token::Ident(s[..len].to_string()),
+                }
+            },
+
+            _ => {
+                // a registered operator may be longer than a builtin one
+                // it starts with, like `<>` and `<`
+                let operator = OPERATORS.iter().chain(&self.symbols).map(|operator| (*operator, *operator))
+                    .chain(ALIASES)
+                    .filter(|(spelling, _)| s.starts_with(spelling))
+                    .max_by_key(|(spelling, _)| spelling.len());
+                match operator {
+                    Some((spelling, operator)) => {
+                        self.advance(&s[spelling.len()..]);
+                        Token::Operator(operator)
+                    },
+                    None => Token::Invalid(CalcError::UnexpectedToken("unknown character")),
+                }
+            },
+        }
+    }
+}
+
+pub mod math {
+    pub fn constant(name: &str) -> Option<f64> {
+        use std::f64::consts;
+
+        match name {
+            "pi" => Some(consts::PI),
+            "e" => Some(consts::E),
+            "tau" => Some(consts::TAU),
+            "phi" => Some(1.618_033_988_749_895),
+            "inf" => Some(f64::INFINITY),
+            "nan" => Some(f64::NAN),
+            "true" => Some(1.0),
+            "false" => Some(0.0),
+            _ => None,
+        }
+    }
+
+    const LANCZOS_G: f64 = 7.0;
+    const LANCZOS_COEFFICIENTS: [f64; 9] = [
+        0.999_999_999_999_809_9,
+        676.520_368_121_885_1,
+        -1_259.139_216_722_402_8,
+        771.323_428_777_653_1,
+        -176.615_029_162_140_6,
+        12.507_343_278_686_905,
+        -0.138_571_095_265_720_12,
+        9.984_369_578_019_572e-6,
+        1.505_632_735_149_311_6e-7,
+    ];
+
+    /// The Lanczos sum and `t` for `x >= 0.5`, with which
+    /// `gamma(x) = sqrt(2 pi) t^(x - 0.5) e^-t sum`.
+    fn lanczos(x: f64) -> (f64, f64) {
+        let x = x - 1.0;
+        let sum = LANCZOS_COEFFICIENTS[1..].iter().enumerate()
+            .fold(LANCZOS_COEFFICIENTS[0], |sum, (i, c)| sum + c / (x + i as f64 + 1.0));
+        (sum, x + LANCZOS_G + 0.5)
+    }
+
+    /// Lanczos approximation of the gamma function (g = 7, n = 9).
+    pub fn gamma(x: f64) -> f64 {
+        if x < 0.5 {
+            // reflection formula
+            std::f64::consts::PI / ((std::f64::consts::PI * x).sin() * gamma(1.0 - x))
+        } else {
+            let (sum, t) = lanczos(x);
+            // in halves, which overflow later than `t^(x - 0.5)`
+            let half = t.powf((x - 0.5) / 2.0);
+            (2.0 * std::f64::consts::PI).sqrt() * half * (-t).exp() * half * sum
+        }
+    }
+
+    /// `ln |gamma(x)|`, which stays finite far beyond where `gamma`
+    /// overflows.
+    pub fn ln_gamma(x: f64) -> f64 {
+        use std::f64::consts::PI;
+
+        if x < 0.5 {
+            (PI / (PI * x).sin().abs()).ln() - ln_gamma(1.0 - x)
+        } else {
+            let (sum, t) = lanczos(x);
+            0.5 * (2.0 * PI).ln() + (x - 0.5) * t.ln() - t + sum.ln()
+        }
+    }
+
+    /// The error function, by its Taylor series near zero and through
+    /// `erfc` elsewhere.
+    pub fn erf(x: f64) -> f64 {
+        if x.abs() >= 1.0 {
+            return x.signum() * (1.0 - erfc(x.abs()));
+        }
+        let mut term = x;
+        let mut sum = x;
+        for n in 1.. {
+            term *= -x * x / n as f64;
+            let next = sum + term / (2 * n + 1) as f64;
+            if next == sum {
+                break;
+            }
+            sum = next;
+        }
+        sum * 2.0 / std::f64::consts::PI.sqrt()
+    }
+
+    /// The complementary error function `1 - erf(x)`, by its continued
+    /// fraction away from zero, where the subtraction would cancel.
+    pub fn erfc(x: f64) -> f64 {
+        if x < 0.0 {
+            return 2.0 - erfc(-x);
+        }
+        if x < 1.0 {
+            return 1.0 - erf(x);
+        }
+        let fraction = (1..=200).rev().fold(x, |fraction, k| x + k as f64 / 2.0 / fraction);
+        (-x * x).exp() / std::f64::consts::PI.sqrt() / fraction
+    }
+
+    /// Rounds to 12 significant digits, hiding the rounding errors of
+    /// approximate results like `5.000000000000001`.
+    pub fn significant(x: f64) -> f64 {
+        format!("{:.11e}", x).parse().unwrap()
+    }
+
+    /// The integral of `f` from `a` to `b` by adaptive Simpson's rule, which
+    /// halves intervals until the estimated error is below `tolerance`, or
+    /// it has evaluated `f` a hundred thousand times; `None` as soon as `f`
+    /// has no value.
+    pub fn integrate(mut f: impl FnMut(f64) -> Option<f64>, a: f64, b: f64, tolerance: f64) -> Option<f64> {
+        struct Interval {
+            a: (f64, f64),
+            b: (f64, f64),
+            middle: (f64, f64),
+            area: f64,
+            tolerance: f64,
+        }
+
+        let mut budget = 100_000;
+        let mut total = 0.0;
+        let m = (a + b) / 2.0;
+        let (fa, fb, fm) = (f(a)?, f(b)?, f(m)?);
+        let mut intervals = vec![Interval {
+            a: (a, fa),
+            b: (b, fb),
+            middle: (m, fm),
+            area: (b - a) / 6.0 * (fa + 4.0 * fm + fb),
+            tolerance,
+        }];
+        while let Some(Interval { a, b, middle, area, tolerance }) = intervals.pop() {
+            let (left, right) = ((a.0 + middle.0) / 2.0, (middle.0 + b.0) / 2.0);
+            let (left, right) = ((left, f(left)?), (right, f(right)?));
+            let left_area = (middle.0 - a.0) / 6.0 * (a.1 + 4.0 * left.1 + middle.1);
+            let right_area = (b.0 - middle.0) / 6.0 * (middle.1 + 4.0 * right.1 + b.1);
+            let error = left_area + right_area - area;
+            budget -= 2;
+            if budget <= 0 || error.abs() <= 15.0 * tolerance {
+                total += left_area + right_area + error / 15.0;
+                continue;
+            }
+            let tolerance = tolerance / 2.0;
+            intervals.push(Interval { a, b: middle, middle: left, area: left_area, tolerance });
+            intervals.push(Interval { a: middle, b, middle: right, area: right_area, tolerance });
+        }
+        Some(total)
+    }
+
+    /// Zeros of `f`, which gives `None` where it has no real value: sign
+    /// changes found by scanning from -100 to 100 are narrowed down by
+    /// bisection, and failing that Newton's method starts from 1.
+    pub fn find_roots(mut f: impl FnMut(f64) -> Option<f64>) -> Vec<f64> {
+        const STEPS: i32 = 4000;
+        const TOLERANCE: f64 = 1e-9;
+        let mut roots: Vec<f64> = Vec::new();
+        let push = |roots: &mut Vec<f64>, root: f64| {
+            if ! roots.iter().any(|other| (other - root).abs() <= TOLERANCE * (1.0 + root.abs())) {
+                roots.push(significant(root));
+            }
+        };
+
+        let mut previous = None;
+        for i in 0..=STEPS {
+            let x = -100.0 + 200.0 * i as f64 / STEPS as f64;
+            let y = f(x).filter(|y| y.is_finite());
+            match (previous, y) {
+                (_, Some(0.0)) => push(&mut roots, x),
+                (Some((mut a, fa)), Some(y)) if (fa < 0.0) != (y < 0.0) && fa != 0.0 => {
+                    let mut b = x;
+                    for _ in 0..200 {
+                        let middle = (a + b) / 2.0;
+                        match f(middle) {
+                            Some(fm) if (fm < 0.0) == (fa < 0.0) => a = middle,
+                            Some(_) => b = middle,
+                            None => break,
+                        }
+                    }
+                    // a jump across a pole, like that of `1/x`, is no zero
+                    if f(a).is_some_and(|fa| fa.abs() < 1e-6) {
+                        push(&mut roots, a);
+                    }
+                },
+                _ => (),
+            }
+            previous = y.map(|y| (x, y));
+        }
+
+        if roots.is_empty() {
+            let mut x: f64 = 1.0;
+            let mut step = f64::INFINITY;
+            for _ in 0..100 {
+                let h = 1e-7 * (1.0 + x.abs());
+                let (Some(y), Some(y2)) = (f(x), f(x + h)) else { break };
+                step = y * h / (y2 - y);
+                x -= step;
+                if step.abs() <= TOLERANCE * (1.0 + x.abs()) {
+                    break;
+                }
+            }
+            // only a converging sequence, not one running off like for
+            // `1/x`, ends at a zero
+            if step.abs() <= TOLERANCE * (1.0 + x.abs()) && f(x).is_some_and(|y| y.abs() < 1e-6) {
+                push(&mut roots, x);
+            }
+        }
+        roots.sort_by(f64::total_cmp);
+        roots
+    }
+}
+
+pub mod complex {
+    use std::fmt;
+    use std::ops::{Add, Div, Mul, Neg, Sub};
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct Complex {
+        pub re: f64,
+        pub im: f64,
+    }
+
+    impl Complex {
+        pub const I: Complex = Complex { re: 0.0, im: 1.0 };
+        const ONE: Complex = Complex { re: 1.0, im: 0.0 };
+
+        pub fn new(re: f64, im: f64) -> Self {
+            Complex { re, im }
+        }
+
+        pub fn abs(self) -> f64 {
+            self.re.hypot(self.im)
+        }
+
+        pub fn arg(self) -> f64 {
+            self.im.atan2(self.re)
+        }
+
+        pub fn conj(self) -> Self {
+            Complex::new(self.re, - self.im)
+        }
+
+        pub fn exp(self) -> Self {
+            let scale = self.re.exp();
+            Complex::new(scale * self.im.cos(), scale * self.im.sin())
+        }
+
+        /// The principal logarithm, its imaginary part is in `(-π, π]`.
+        pub fn ln(self) -> Self {
+            Complex::new(self.abs().ln(), self.arg())
+        }
+
+        /// The principal square root, its real part is never negative.
+        pub fn sqrt(self) -> Self {
+            // computed from the modulus rather than in polar form, so that
+            // `sqrt(-4)` is exactly `2i`
+            let abs = self.abs();
+            let re = ((abs + self.re) / 2.0).sqrt();
+            let im = ((abs - self.re) / 2.0).sqrt();
+            Complex::new(re, if self.im < 0.0 { - im } else { im })
+        }
+
+        pub fn pow(self, exponent: Self) -> Self {
+            // integer powers are multiplied out, so that `i^2` is exactly `-1`
+            if exponent.im == 0.0 && exponent.re.fract() == 0.0 && exponent.re.abs() < i64::MAX as f64 {
+                let mut n = exponent.re.abs() as u64;
+                let (mut base, mut result) = (self, Complex::ONE);
+                while n > 0 {
+                    if n & 1 == 1 {
+                        result = result * base;
+                    }
+                    base = base * base;
+                    n >>= 1;
+                }
+                return if exponent.re < 0.0 { Complex::ONE / result } else { result };
+            }
+            if self.re == 0.0 && self.im == 0.0 {
+                return Complex::new(0f64.powf(exponent.re), 0.0);
+            }
+            (exponent * self.ln()).exp()
+        }
+    }
+
+    /// All roots of the polynomial with the real `coefficients`, highest
+    /// power first, as many as its degree: by formula up to quadratics,
+    /// otherwise by the Durand-Kerner iteration. Real roots come first.
+    pub fn polynomial_roots(coefficients: &[f64]) -> Vec<Complex> {
+        let start = coefficients.iter().position(|c| *c != 0.0).unwrap_or(coefficients.len());
+        // zero roots are split off, which the iteration finds only slowly
+        let end = coefficients.iter().rposition(|c| *c != 0.0).map_or(start, |end| end + 1);
+        let zeros = vec![Complex::new(0.0, 0.0); coefficients.len() - end];
+        let coefficients: Vec<_> = coefficients[start..end].iter().map(|c| c / coefficients[start]).collect();
+        let degree = coefficients.len().saturating_sub(1);
+        let mut roots = match degree {
+            0 => Vec::new(),
+            1 => vec![Complex::new(- coefficients[1], 0.0)],
+            2 => {
+                let (b, c) = (Complex::new(coefficients[1], 0.0), Complex::new(coefficients[2], 0.0));
+                let root = (b * b - Complex::new(4.0, 0.0) * c).sqrt();
+                let half = Complex::new(0.5, 0.0);
+                vec![(- b - root) * half, (- b + root) * half]
+            },
+            _ => {
+                let value = |z: Complex| coefficients.iter().fold(Complex::new(0.0, 0.0), |sum, c| sum * z + Complex::new(*c, 0.0));
+                let seed = Complex::new(0.4, 0.9);
+                let mut roots: Vec<_> = (0..degree).map(|k| seed.pow(Complex::new(k as f64, 0.0))).collect();
+                for _ in 0..1000 {
+                    let mut change: f64 = 0.0;
+                    for i in 0..degree {
+                        let denominator = (0..degree).filter(|j| *j != i)
+                            .fold(Complex::ONE, |product, j| product * (roots[i] - roots[j]));
+                        let step = value(roots[i]) / denominator;
+                        roots[i] = roots[i] - step;
+                        change = change.max(step.abs());
+                    }
+                    if change < 1e-15 {
+                        break;
+                    }
+                }
+                roots
+            },
+        };
+        roots.extend(zeros);
+        // rounding errors leave tiny imaginary parts on real roots
+        for root in roots.iter_mut() {
+            *root = Complex::new(crate::math::significant(root.re) + 0.0, crate::math::significant(root.im) + 0.0);
+            if root.im.abs() < 1e-10 * (1.0 + root.re.abs()) {
+                root.im = 0.0;
+            }
+        }
+        roots.sort_by(|a, b| (a.im != 0.0).cmp(&(b.im != 0.0)).then(a.re.total_cmp(&b.re)).then(a.im.total_cmp(&b.im)));
+        roots
+    }
+
+    impl Add for Complex {
+        type Output = Complex;
+        fn add(self, other: Complex) -> Complex {
+            Complex::new(self.re + other.re, self.im + other.im)
+        }
+    }
+
+    impl Sub for Complex {
+        type Output = Complex;
+        fn sub(self, other: Complex) -> Complex {
+            Complex::new(self.re - other.re, self.im - other.im)
+        }
+    }
+
+    impl Mul for Complex {
+        type Output = Complex;
+        fn mul(self, other: Complex) -> Complex {
+            Complex::new(
+                self.re * other.re - self.im * other.im,
+                self.re * other.im + self.im * other.re,
+            )
+        }
+    }
+
+    impl Div for Complex {
+        type Output = Complex;
+        fn div(self, other: Complex) -> Complex {
+            let norm = other.re * other.re + other.im * other.im;
+            Complex::new(
+                (self.re * other.re + self.im * other.im) / norm,
+                (self.im * other.re - self.re * other.im) / norm,
+            )
+        }
+    }
+
+    impl Neg for Complex {
+        type Output = Complex;
+        fn neg(self) -> Complex {
+            Complex::new(- self.re, - self.im)
+        }
+    }
+
+    impl fmt::Display for Complex {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            if self.re == 0.0 {
+                write!(f, "{}i", self.im)
+            } else if self.im < 0.0 {
+                write!(f, "{}-{}i", self.re, - self.im)
+            } else {
+                write!(f, "{}+{}i", self.re, self.im)
+            }
+        }
+    }
+}
+
+pub mod bigint {
+    use std::cmp::Ordering;
+    use std::fmt;
+    use std::ops::{Add, Mul, Neg, Sub};
+
+    /// the size beyond which exact powers give up and leave the result to
+    /// floating point, about 80000 decimal digits
+    pub const MAX_BITS: u64 = 1 << 18;
+
+    /// An arbitrary precision integer, stored as its sign and magnitude.
+    /// The magnitude is in base 2^32, least significant limb first, without
+    /// leading zero limbs, so zero is the empty magnitude.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct BigInt {
+        negative: bool,
+        magnitude: Vec<u32>,
+    }
+
+    fn trim(mut magnitude: Vec<u32>) -> Vec<u32> {
+        while magnitude.last() == Some(&0) {
+            magnitude.pop();
+        }
+        magnitude
+    }
+
+    fn cmp_magnitude(a: &[u32], b: &[u32]) -> Ordering {
+        a.len().cmp(&b.len()).then_with(|| a.iter().rev().cmp(b.iter().rev()))
+    }
+
+    fn add_magnitude(a: &[u32], b: &[u32]) -> Vec<u32> {
+        let (long, short) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+        let mut result = Vec::with_capacity(long.len() + 1);
+        let mut carry = 0;
+        for (i, limb) in long.iter().enumerate() {
+            let sum = *limb as u64 + *short.get(i).unwrap_or(&0) as u64 + carry;
+            result.push(sum as u32);
+            carry = sum >> 32;
+        }
+        result.push(carry as u32);
+        trim(result)
+    }
+
+    /// `a - b`, where `a` is at least `b`.
+    fn sub_magnitude(a: &[u32], b: &[u32]) -> Vec<u32> {
+        let mut result = Vec::with_capacity(a.len());
+        let mut borrow = 0;
+        for (i, limb) in a.iter().enumerate() {
+            let diff = *limb as i64 - *b.get(i).unwrap_or(&0) as i64 - borrow;
+            borrow = if diff < 0 { 1 } else { 0 };
+            result.push((diff + (borrow << 32)) as u32);
+        }
+        trim(result)
+    }
+
+    fn mul_magnitude(a: &[u32], b: &[u32]) -> Vec<u32> {
+        let mut result = vec![0; a.len() + b.len()];
+        for (i, x) in a.iter().enumerate() {
+            let mut carry = 0;
+            for (j, y) in b.iter().enumerate() {
+                let product = *x as u64 * *y as u64 + result[i + j] as u64 + carry;
+                result[i + j] = product as u32;
+                carry = product >> 32;
+            }
+            result[i + b.len()] = carry as u32;
+        }
+        trim(result)
+    }
+
+    fn divrem_small(a: &[u32], divisor: u32) -> (Vec<u32>, u32) {
+        let mut quotient = vec![0; a.len()];
+        let mut remainder = 0;
+        for (i, limb) in a.iter().enumerate().rev() {
+            let current = remainder << 32 | *limb as u64;
+            quotient[i] = (current / divisor as u64) as u32;
+            remainder = current % divisor as u64;
+        }
+        (trim(quotient), remainder as u32)
+    }
+
+    /// Binary long division, `b` must not be zero.
+    fn divrem_magnitude(a: &[u32], b: &[u32]) -> (Vec<u32>, Vec<u32>) {
+        if let [divisor] = b {
+            let (quotient, remainder) = divrem_small(a, *divisor);
+            return (quotient, trim(vec![remainder]));
+        }
+        let mut quotient = vec![0; a.len()];
+        let mut remainder = Vec::new();
+        for bit in (0..a.len() * 32).rev() {
+            // remainder = remainder * 2 + the next bit of `a`
+            let mut carry = a[bit / 32] >> (bit % 32) & 1;
+            for limb in remainder.iter_mut() {
+                let next = *limb >> 31;
+                *limb = *limb << 1 | carry;
+                carry = next;
+            }
+            if carry > 0 {
+                remainder.push(carry);
+            }
+            if cmp_magnitude(&remainder, b) != Ordering::Less {
+                remainder = sub_magnitude(&remainder, b);
+                quotient[bit / 32] |= 1 << (bit % 32);
+            }
+        }
+        (trim(quotient), remainder)
+    }
+
+    impl BigInt {
+        fn new(negative: bool, magnitude: Vec<u32>) -> Self {
+            let magnitude = trim(magnitude);
+            BigInt { negative: negative && ! magnitude.is_empty(), magnitude }
+        }
+
+        pub fn is_zero(&self) -> bool {
+            self.magnitude.is_empty()
+        }
+
+        pub fn is_negative(&self) -> bool {
+            self.negative
+        }
+
+        pub fn abs(&self) -> BigInt {
+            BigInt::new(false, self.magnitude.clone())
+        }
+
+        /// The number of bits of the magnitude.
+        pub fn bits(&self) -> u64 {
+            match self.magnitude.last() {
+                Some(top) => self.magnitude.len() as u64 * 32 - top.leading_zeros() as u64,
+                None => 0,
+            }
+        }
+
+        pub fn pow(&self, mut exponent: u64) -> BigInt {
+            let (mut base, mut result) = (self.clone(), BigInt::from(1));
+            while exponent > 0 {
+                if exponent & 1 == 1 {
+                    result = &result * &base;
+                }
+                exponent >>= 1;
+                if exponent > 0 {
+                    base = &base * &base;
+                }
+            }
+            result
+        }
+
+        /// The quotient rounded toward zero and the remainder, which has the
+        /// sign of `self`; `None` when dividing by zero.
+        pub fn divrem(&self, other: &BigInt) -> Option<(BigInt, BigInt)> {
+            if other.is_zero() {
+                return None;
+            }
+            let (quotient, remainder) = divrem_magnitude(&self.magnitude, &other.magnitude);
+            Some((
+                BigInt::new(self.negative != other.negative, quotient),
+                BigInt::new(self.negative, remainder),
+            ))
+        }
+
+        /// The remainder of dividing by `modulus`, never negative; `modulus`
+        /// must not be zero.
+        pub fn modulo(&self, modulus: &BigInt) -> BigInt {
+            let modulus = modulus.abs();
+            let (_, remainder) = self.divrem(&modulus).unwrap();
+            if remainder.is_negative() { &remainder + &modulus } else { remainder }
+        }
+
+        /// `self ^ exponent` modulo `modulus` by square-and-multiply, never
+        /// negative; `None` for a zero modulus or a negative exponent.
+        pub fn pow_mod(&self, exponent: &BigInt, modulus: &BigInt) -> Option<BigInt> {
+            if modulus.is_zero() || exponent.is_negative() {
+                return None;
+            }
+            let (base, mut result) = (self.modulo(modulus), BigInt::from(1).modulo(modulus));
+            for bit in (0..exponent.bits()).rev() {
+                result = (&result * &result).modulo(modulus);
+                if exponent.magnitude[bit as usize / 32] >> (bit % 32) & 1 == 1 {
+                    result = (&result * &base).modulo(modulus);
+                }
+            }
+            Some(result)
+        }
+
+        /// The `x` with `self * x` equal to 1 modulo `modulus`, by the
+        /// extended Euclidean algorithm; `None` unless the two are coprime.
+        pub fn inverse_mod(&self, modulus: &BigInt) -> Option<BigInt> {
+            let (mut r0, mut r1) = (modulus.abs(), self.modulo(modulus));
+            let (mut t0, mut t1) = (BigInt::from(0), BigInt::from(1));
+            while ! r1.is_zero() {
+                let (quotient, remainder) = r0.divrem(&r1).unwrap();
+                let t = &t0 - &(&quotient * &t1);
+                (r0, r1, t0, t1) = (r1, remainder, t1, t);
+            }
+            (r0 == BigInt::from(1)).then(|| t0.modulo(modulus))
+        }
+
+        /// The greatest common divisor, never negative, by Euclid's
+        /// algorithm; 0 only when both are.
+        pub fn gcd(&self, other: &BigInt) -> BigInt {
+            let (mut a, mut b) = (self.abs(), other.abs());
+            while ! b.is_zero() {
+                let (_, remainder) = a.divrem(&b).unwrap();
+                (a, b) = (b, remainder);
+            }
+            a
+        }
+
+        /// Parses an optional `-` followed by decimal digits.
+        pub fn parse(s: &str) -> Option<BigInt> {
+            let (negative, digits) = match s.strip_prefix('-') {
+                Some(digits) => (true, digits),
+                None => (false, s),
+            };
+            if digits.is_empty() || ! digits.bytes().all(|b| b.is_ascii_digit()) {
+                return None;
+            }
+            // nine digits at a time still fit in a limb
+            let head = digits.len() % 9;
+            let chunks = std::iter::once(&digits[..head])
+                .chain((head..digits.len()).step_by(9).map(|start| &digits[start..start + 9]));
+            let mut magnitude = Vec::new();
+            for chunk in chunks.filter(|chunk| ! chunk.is_empty()) {
+                magnitude = mul_magnitude(&magnitude, &[10u32.pow(chunk.len() as u32)]);
+                magnitude = add_magnitude(&magnitude, &[chunk.parse().unwrap()]);
+            }
+            Some(BigInt::new(negative, magnitude))
+        }
+
+        /// The integer value of a finite float without a fractional part.
+        pub fn from_f64(x: f64) -> Option<BigInt> {
+            if x.is_finite() && x.fract() == 0.0 {
+                BigInt::parse(&format!("{:.0}", x))
+            } else {
+                None
+            }
+        }
+
+        pub fn to_f64(&self) -> f64 {
+            self.to_string().parse().unwrap()
+        }
+
+        pub fn to_i64(&self) -> Option<i64> {
+            if self.bits() > 63 {
+                return None;
+            }
+            let magnitude = self.magnitude.iter().rev().fold(0, |result, limb| result << 32 | *limb as i64);
+            Some(if self.negative { - magnitude } else { magnitude })
+        }
+
+        pub fn from_u64(n: u64) -> BigInt {
+            BigInt::new(false, vec![n as u32, (n >> 32) as u32])
+        }
+
+        pub fn to_u64(&self) -> Option<u64> {
+            if self.negative || self.bits() > 64 {
+                return None;
+            }
+            Some(self.magnitude.iter().rev().fold(0, |result, limb| result << 32 | *limb as u64))
+        }
+    }
+
+    impl From<i64> for BigInt {
+        fn from(n: i64) -> Self {
+            let magnitude = n.unsigned_abs();
+            BigInt::new(n < 0, vec![magnitude as u32, (magnitude >> 32) as u32])
+        }
+    }
+
+    impl Ord for BigInt {
+        fn cmp(&self, other: &BigInt) -> Ordering {
+            match (self.negative, other.negative) {
+                (false, true) => Ordering::Greater,
+                (true, false) => Ordering::Less,
+                (false, false) => cmp_magnitude(&self.magnitude, &other.magnitude),
+                (true, true) => cmp_magnitude(&other.magnitude, &self.magnitude),
+            }
+        }
+    }
+
+    impl PartialOrd for BigInt {
+        fn partial_cmp(&self, other: &BigInt) -> Option<Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+
+    impl Add for &BigInt {
+        type Output = BigInt;
+        fn add(self, other: &BigInt) -> BigInt {
+            if self.negative == other.negative {
+                return BigInt::new(self.negative, add_magnitude(&self.magnitude, &other.magnitude));
+            }
+            match cmp_magnitude(&self.magnitude, &other.magnitude) {
+                Ordering::Less => BigInt::new(other.negative, sub_magnitude(&other.magnitude, &self.magnitude)),
+                _ => BigInt::new(self.negative, sub_magnitude(&self.magnitude, &other.magnitude)),
+            }
+        }
+    }
+
+    impl Sub for &BigInt {
+        type Output = BigInt;
+        fn sub(self, other: &BigInt) -> BigInt {
+            self + &- other
+        }
+    }
+
+    impl Mul for &BigInt {
+        type Output = BigInt;
+        fn mul(self, other: &BigInt) -> BigInt {
+            BigInt::new(self.negative != other.negative, mul_magnitude(&self.magnitude, &other.magnitude))
+        }
+    }
+
+    impl Neg for &BigInt {
+        type Output = BigInt;
+        fn neg(self) -> BigInt {
+            BigInt::new(! self.negative, self.magnitude.clone())
+        }
+    }
+
+    impl BigInt {
+        /// Writes the digits in base `radix`, from 2 to 36, in lower case
+        /// and without a prefix.
+        pub fn to_str_radix(&self, radix: u32) -> String {
+            // as many digits per chunk as fit into a `u32`
+            let width = (1..).take_while(|width| (radix as u64).pow(*width) <= u32::MAX as u64).last().unwrap();
+            let mut chunks = Vec::new();
+            let mut magnitude = self.magnitude.clone();
+            while ! magnitude.is_empty() {
+                let (quotient, chunk) = divrem_small(&magnitude, radix.pow(width));
+                chunks.push(chunk);
+                magnitude = quotient;
+            }
+            let digits = |mut chunk: u32, width: u32| {
+                let mut digits: Vec<_> = (0..width).map(|_| {
+                    let digit = std::char::from_digit(chunk % radix, radix).unwrap();
+                    chunk /= radix;
+                    digit
+                }).collect();
+                digits.reverse();
+                digits.into_iter().collect::<String>()
+            };
+            let mut text = if self.negative { "-".to_string() } else { String::new() };
+            match chunks.split_last() {
+                Some((top, rest)) => {
+                    text.push_str(digits(*top, width).trim_start_matches('0'));
+                    rest.iter().rev().for_each(|chunk| text.push_str(&digits(*chunk, width)));
+                },
+                None => text.push('0'),
+            }
+            text
+        }
+    }
+
+    impl fmt::Display for BigInt {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            // split into nine digit chunks, least significant first
+            let mut chunks = Vec::new();
+            let mut magnitude = self.magnitude.clone();
+            while ! magnitude.is_empty() {
+                let (quotient, chunk) = divrem_small(&magnitude, 1_000_000_000);
+                chunks.push(chunk);
+                magnitude = quotient;
+            }
+            if self.negative {
+                write!(f, "-")?;
+            }
+            match chunks.split_last() {
+                Some((top, rest)) => {
+                    write!(f, "{}", top)?;
+                    rest.iter().rev().try_for_each(|chunk| write!(f, "{:09}", chunk))
+                },
+                None => write!(f, "0"),
+            }
+        }
+    }
+
+    /// Written as its decimal digits, which keep an integer of any size.
+    #[cfg(feature = "serde")]
+    impl serde::Serialize for BigInt {
+        fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.collect_str(self)
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    impl<'de> serde::Deserialize<'de> for BigInt {
+        fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            let digits = <String as serde::Deserialize>::deserialize(deserializer)?;
+            BigInt::parse(&digits).ok_or_else(|| serde::de::Error::custom("invalid integer"))
+        }
+    }
+}
+
+pub mod decimal {
+    use std::cmp::Ordering;
+    use std::fmt;
+    use std::ops::{Add, Mul, Neg, Sub};
+
+    use crate::bigint::{BigInt, MAX_BITS};
+
+    /// fractional digits kept by divisions that do not terminate, like
+    /// the `scale` of `bc -l`
+    const DIVISION_DIGITS: u32 = 20;
+
+    const MAX_EXPONENT: i32 = 4096;
+
+    /// An exact decimal number `mantissa / 10^scale`, kept without
+    /// trailing fractional zeros.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct Decimal {
+        mantissa: BigInt,
+        scale: u32,
+    }
+
+    fn ten_pow(n: u32) -> BigInt {
+        BigInt::from(10).pow(n as u64)
+    }
+
+    impl Decimal {
+        fn new(mut mantissa: BigInt, mut scale: u32) -> Self {
+            let ten = BigInt::from(10);
+            while scale > 0 {
+                match mantissa.divrem(&ten) {
+                    Some((quotient, remainder)) if remainder.is_zero() => mantissa = quotient,
+                    _ => break,
+                }
+                scale -= 1;
+            }
+            Decimal { mantissa, scale }
+        }
+
+        /// Parses an optional `-`, digits, an optional fractional part and
+        /// an optional exponent, like `-1.5e3`.
+        pub fn parse(s: &str) -> Option<Decimal> {
+            if let Some((s, exponent)) = s.split_once(['e', 'E']) {
+                let exponent: i32 = exponent.parse().ok()?;
+                // larger exponents are left to floating point
+                if exponent.abs() > MAX_EXPONENT {
+                    return None;
+                }
+                return Decimal::parse(s).map(|decimal| decimal.shift(exponent));
+            }
+            match s.split_once('.') {
+                Some((integer, fraction)) if ! fraction.starts_with(['-', '+']) => {
+                    let mantissa = BigInt::parse(&format!("{}{}", integer, fraction))?;
+                    Some(Decimal::new(mantissa, fraction.len() as u32))
+                },
+                Some(_) => None,
+                None => BigInt::parse(s).map(|mantissa| Decimal::new(mantissa, 0)),
+            }
+        }
+
+        /// The decimal written by the shortest representation of `x`, so
+        /// that the literal `0.1` becomes exactly one tenth.
+        pub fn from_f64(x: f64) -> Option<Decimal> {
+            Decimal::parse(&x.to_string())
+        }
+
+        pub fn to_f64(&self) -> f64 {
+            self.to_string().parse().unwrap()
+        }
+
+        pub fn is_negative(&self) -> bool {
+            self.mantissa.is_negative()
+        }
+
+        /// The integer value, if there is no fractional part.
+        pub fn to_integer(&self) -> Option<BigInt> {
+            (self.scale == 0).then(|| self.mantissa.clone())
+        }
+
+        /// The mantissas of both numbers brought to a common scale.
+        fn aligned(&self, other: &Decimal) -> (BigInt, BigInt, u32) {
+            let scale = self.scale.max(other.scale);
+            (
+                &self.mantissa * &ten_pow(scale - self.scale),
+                &other.mantissa * &ten_pow(scale - other.scale),
+                scale,
+            )
+        }
+
+        /// `self * 10^places`, exactly.
+        pub fn shift(&self, places: i32) -> Decimal {
+            if places >= 0 {
+                let scale = self.scale.saturating_sub(places as u32);
+                let factor = ten_pow(places as u32 - (self.scale - scale));
+                Decimal::new(&self.mantissa * &factor, scale)
+            } else {
+                Decimal::new(self.mantissa.clone(), self.scale + places.unsigned_abs())
+            }
+        }
+
+        /// The quotient rounded half away from zero to `DIVISION_DIGITS`
+        /// places; `None` when dividing by zero.
+        pub fn div(&self, other: &Decimal) -> Option<Decimal> {
+            let (a, b, _) = self.aligned(other);
+            let (quotient, remainder) = (&a * &ten_pow(DIVISION_DIGITS)).divrem(&b)?;
+            let round = &remainder.abs() * &BigInt::from(2) >= b.abs();
+            let quotient = match (round, a.is_negative() != b.is_negative()) {
+                (false, _) => quotient,
+                (true, false) => &quotient + &BigInt::from(1),
+                (true, true) => &quotient - &BigInt::from(1),
+            };
+            Some(Decimal::new(quotient, DIVISION_DIGITS))
+        }
+
+        /// Rounds half away from zero to `places` decimal places.
+        pub fn round(&self, places: u32) -> Decimal {
+            if self.scale <= places {
+                return self.clone();
+            }
+            let divisor = ten_pow(self.scale - places);
+            let (quotient, remainder) = self.mantissa.divrem(&divisor).unwrap();
+            let quotient = if &remainder.abs() * &BigInt::from(2) < divisor {
+                quotient
+            } else if self.is_negative() {
+                &quotient - &BigInt::from(1)
+            } else {
+                &quotient + &BigInt::from(1)
+            };
+            Decimal::new(quotient, places)
+        }
+
+        /// The quotient rounded toward zero and the remainder, which has the
+        /// sign of `self`; `None` when dividing by zero.
+        pub fn divrem(&self, other: &Decimal) -> Option<(BigInt, Decimal)> {
+            let (a, b, scale) = self.aligned(other);
+            let (quotient, remainder) = a.divrem(&b)?;
+            Some((quotient, Decimal::new(remainder, scale)))
+        }
+
+        /// Integer powers, `None` when dividing by zero or when the result
+        /// would be unreasonably large.
+        pub fn pow(&self, exponent: i64) -> Option<Decimal> {
+            let size = self.mantissa.bits().max(1).checked_mul(exponent.unsigned_abs())?;
+            if size > MAX_BITS {
+                return None;
+            }
+            let power = Decimal::new(
+                self.mantissa.pow(exponent.unsigned_abs()),
+                self.scale.checked_mul(u32::try_from(exponent.unsigned_abs()).ok()?)?,
+            );
+            if exponent < 0 { Decimal::from(1).div(&power) } else { Some(power) }
+        }
+    }
+
+    impl From<i64> for Decimal {
+        fn from(n: i64) -> Self {
+            Decimal::new(BigInt::from(n), 0)
+        }
+    }
+
+    impl From<BigInt> for Decimal {
+        fn from(n: BigInt) -> Self {
+            Decimal::new(n, 0)
+        }
+    }
+
+    impl Ord for Decimal {
+        fn cmp(&self, other: &Decimal) -> Ordering {
+            let (a, b, _) = self.aligned(other);
+            a.cmp(&b)
+        }
+    }
+
+    impl PartialOrd for Decimal {
+        fn partial_cmp(&self, other: &Decimal) -> Option<Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+
+    impl Add for &Decimal {
+        type Output = Decimal;
+        fn add(self, other: &Decimal) -> Decimal {
+            let (a, b, scale) = self.aligned(other);
+            Decimal::new(&a + &b, scale)
+        }
+    }
+
+    impl Sub for &Decimal {
+        type Output = Decimal;
+        fn sub(self, other: &Decimal) -> Decimal {
+            let (a, b, scale) = self.aligned(other);
+            Decimal::new(&a - &b, scale)
+        }
+    }
+
+    impl Mul for &Decimal {
+        type Output = Decimal;
+        fn mul(self, other: &Decimal) -> Decimal {
+            Decimal::new(&self.mantissa * &other.mantissa, self.scale + other.scale)
+        }
+    }
+
+    impl Neg for &Decimal {
+        type Output = Decimal;
+        fn neg(self) -> Decimal {
+            Decimal::new(- &self.mantissa, self.scale)
+        }
+    }
+
+    /// A precision, like in `{:.2}`, pads the fractional part with zeros.
+    impl fmt::Display for Decimal {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            let digits = self.mantissa.abs().to_string();
+            let sign = if self.is_negative() { "-" } else { "" };
+            let scale = self.scale as usize;
+            let mut text = if scale == 0 {
+                format!("{}{}", sign, digits)
+            } else if digits.len() > scale {
+                let (integer, fraction) = digits.split_at(digits.len() - scale);
+                format!("{}{}.{}", sign, integer, fraction)
+            } else {
+                format!("{}0.{}{}", sign, "0".repeat(scale - digits.len()), digits)
+            };
+            if let Some(padding) = f.precision().map(|precision| precision.saturating_sub(scale)).filter(|n| *n > 0) {
+                if scale == 0 {
+                    text.push('.');
+                }
+                text.push_str(&"0".repeat(padding));
+            }
+            f.write_str(&text)
+        }
+    }
+
+    /// Written out like `-1.25`, exactly.
+    #[cfg(feature = "serde")]
+    impl serde::Serialize for Decimal {
+        fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.collect_str(self)
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    impl<'de> serde::Deserialize<'de> for Decimal {
+        fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            let digits = <String as serde::Deserialize>::deserialize(deserializer)?;
+            Decimal::parse(&digits).ok_or_else(|| serde::de::Error::custom("invalid decimal"))
+        }
+    }
+}
+
+pub mod numeric {
+    use std::cmp::Ordering;
+    use std::fmt;
+
+    use crate::bigint::BigInt;
+    use crate::complex::Complex;
+    use crate::decimal::Decimal;
+    use crate::value::Value;
+    use crate::CalcError;
+
+    type Result<T> = std::result::Result<T, CalcError>;
+
+    /// The arithmetic of a kind of number: floats, exact integers,
+    /// decimals or complex numbers. The evaluator applies the operators
+    /// all kinds share through it, and only keeps its own the rules
+    /// particular to one kind, like the exact `^` of integers.
+    pub trait Numeric: Sized + Clone + PartialEq + fmt::Display {
+        fn add(&self, other: &Self) -> Self;
+        fn sub(&self, other: &Self) -> Self;
+        fn mul(&self, other: &Self) -> Self;
+        /// the quotient, or `None` when it is not of this kind, like the
+        /// `7 / 2` of integers
+        fn div(&self, other: &Self) -> Result<Option<Self>>;
+        fn neg(&self) -> Self;
+        /// reads a number literal, `None` if it is not of this kind
+        fn parse(literal: &str) -> Option<Self>;
+        /// the ordering, `None` for unordered numbers like complex ones
+        fn compare(&self, other: &Self) -> Option<Ordering>;
+        fn into_value(self) -> Value;
+    }
+
+    impl Numeric for f64 {
+        fn add(&self, other: &Self) -> Self {
+            self + other
+        }
+
+        fn sub(&self, other: &Self) -> Self {
+            self - other
+        }
+
+        fn mul(&self, other: &Self) -> Self {
+            self * other
+        }
+
+        fn div(&self, other: &Self) -> Result<Option<Self>> {
+            Ok(Some(self / other))
+        }
+
+        fn neg(&self) -> Self {
+            - self
+        }
+
+        fn parse(literal: &str) -> Option<Self> {
+            literal.parse().ok()
+        }
+
+        fn compare(&self, other: &Self) -> Option<Ordering> {
+            self.partial_cmp(other)
+        }
+
+        fn into_value(self) -> Value {
+            Value::Number(self)
+        }
+    }
+
+    /// Inexact quotients have no integer result, and dividing by zero is
+    /// an error.
+    impl Numeric for BigInt {
+        fn add(&self, other: &Self) -> Self {
+            self + other
+        }
+
+        fn sub(&self, other: &Self) -> Self {
+            self - other
+        }
+
+        fn mul(&self, other: &Self) -> Self {
+            self * other
+        }
+
+        fn div(&self, other: &Self) -> Result<Option<Self>> {
+            let (quotient, rest) = self.divrem(other).ok_or(CalcError::DivisionByZero)?;
+            Ok(rest.is_zero().then_some(quotient))
+        }
+
+        fn neg(&self) -> Self {
+            - self
+        }
+
+        fn parse(literal: &str) -> Option<Self> {
+            BigInt::parse(literal)
+        }
+
+        fn compare(&self, other: &Self) -> Option<Ordering> {
+            Some(self.cmp(other))
+        }
+
+        fn into_value(self) -> Value {
+            Value::Integer(self)
+        }
+    }
+
+    /// Quotients that do not terminate are rounded, and dividing by zero
+    /// is an error.
+    impl Numeric for Decimal {
+        fn add(&self, other: &Self) -> Self {
+            self + other
+        }
+
+        fn sub(&self, other: &Self) -> Self {
+            self - other
+        }
+
+        fn mul(&self, other: &Self) -> Self {
+            self * other
+        }
+
+        fn div(&self, other: &Self) -> Result<Option<Self>> {
+            Decimal::div(self, other).ok_or(CalcError::DivisionByZero).map(Some)
+        }
+
+        fn neg(&self) -> Self {
+            - self
+        }
+
+        fn parse(literal: &str) -> Option<Self> {
+            Decimal::parse(literal)
+        }
+
+        fn compare(&self, other: &Self) -> Option<Ordering> {
+            Some(self.cmp(other))
+        }
+
+        fn into_value(self) -> Value {
+            Value::Decimal(self)
+        }
+    }
+
+    /// Literals are real, or imaginary with an `i` suffix like `2.5i`.
+    impl Numeric for Complex {
+        fn add(&self, other: &Self) -> Self {
+            *self + *other
+        }
+
+        fn sub(&self, other: &Self) -> Self {
+            *self - *other
+        }
+
+        fn mul(&self, other: &Self) -> Self {
+            *self * *other
+        }
+
+        fn div(&self, other: &Self) -> Result<Option<Self>> {
+            Ok(Some(*self / *other))
+        }
+
+        fn neg(&self) -> Self {
+            - *self
+        }
+
+        fn parse(literal: &str) -> Option<Self> {
+            match literal.strip_suffix('i') {
+                Some(im) => im.parse().ok().map(|im| Complex::new(0.0, im)),
+                None => literal.parse().ok().map(|re| Complex::new(re, 0.0)),
+            }
+        }
+
+        fn compare(&self, _: &Self) -> Option<Ordering> {
+            None
+        }
+
+        fn into_value(self) -> Value {
+            Value::from(self)
+        }
+    }
+}
+
+pub mod units {
+    use std::fmt;
+
+    use crate::CalcError;
+
+    type Result<T> = std::result::Result<T, CalcError>;
+
+    /// the SI base units and the byte, in the order of the exponents of a
+    /// `Dimension`
+    const BASE_UNITS: [&str; 8] = ["kg", "m", "s", "A", "K", "mol", "cd", "B"];
+
+    /// The exponents of the base units making up a unit, `m/s` is
+    /// `[0, 1, -1, 0, 0, 0, 0, 0]`.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct Dimension([i32; 8]);
+
+    /// A number with a unit, kept in base units.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct Quantity {
+        pub value: f64,
+        pub dimension: Dimension,
+    }
+
+    /// A named unit as a multiple of base units, plus an offset for the
+    /// temperature scales whose zero is not absolute zero.
+    #[derive(Debug)]
+    pub struct Unit {
+        name: &'static str,
+        factor: f64,
+        offset: f64,
+        dimension: Dimension,
+    }
+
+    const MASS: Dimension = Dimension([1, 0, 0, 0, 0, 0, 0, 0]);
+    const LENGTH: Dimension = Dimension([0, 1, 0, 0, 0, 0, 0, 0]);
+    pub const TIME: Dimension = Dimension([0, 0, 1, 0, 0, 0, 0, 0]);
+    const CURRENT: Dimension = Dimension([0, 0, 0, 1, 0, 0, 0, 0]);
+    const TEMPERATURE: Dimension = Dimension([0, 0, 0, 0, 1, 0, 0, 0]);
+    const AMOUNT: Dimension = Dimension([0, 0, 0, 0, 0, 1, 0, 0]);
+    const LUMINOSITY: Dimension = Dimension([0, 0, 0, 0, 0, 0, 1, 0]);
+    const INFORMATION: Dimension = Dimension([0, 0, 0, 0, 0, 0, 0, 1]);
+    const VOLUME: Dimension = Dimension([0, 3, 0, 0, 0, 0, 0, 0]);
+    const FREQUENCY: Dimension = Dimension([0, 0, -1, 0, 0, 0, 0, 0]);
+    const SPEED: Dimension = Dimension([0, 1, -1, 0, 0, 0, 0, 0]);
+    const FORCE: Dimension = Dimension([1, 1, -2, 0, 0, 0, 0, 0]);
+    const PRESSURE: Dimension = Dimension([1, -1, -2, 0, 0, 0, 0, 0]);
+    const ENERGY: Dimension = Dimension([1, 2, -2, 0, 0, 0, 0, 0]);
+    const POWER: Dimension = Dimension([1, 2, -3, 0, 0, 0, 0, 0]);
+    const VOLTAGE: Dimension = Dimension([1, 2, -3, -1, 0, 0, 0, 0]);
+    const RESISTANCE: Dimension = Dimension([1, 2, -3, -2, 0, 0, 0, 0]);
+
+    const fn unit(name: &'static str, factor: f64, dimension: Dimension) -> Unit {
+        Unit { name, factor, offset: 0.0, dimension }
+    }
+
+    const UNITS: &[Unit] = &[
+        unit("m", 1.0, LENGTH),
+        unit("km", 1e3, LENGTH),
+        unit("cm", 1e-2, LENGTH),
+        unit("mm", 1e-3, LENGTH),
+        unit("um", 1e-6, LENGTH),
+        unit("nm", 1e-9, LENGTH),
+        unit("inch", 0.0254, LENGTH),
+        unit("inches", 0.0254, LENGTH),
+        unit("ft", 0.3048, LENGTH),
+        unit("feet", 0.3048, LENGTH),
+        unit("yd", 0.9144, LENGTH),
+        unit("mi", 1609.344, LENGTH),
+        unit("mile", 1609.344, LENGTH),
+        unit("miles", 1609.344, LENGTH),
+        unit("kg", 1.0, MASS),
+        unit("g", 1e-3, MASS),
+        unit("mg", 1e-6, MASS),
+        unit("t", 1e3, MASS),
+        unit("lb", 0.453_592_37, MASS),
+        unit("oz", 0.028_349_523_125, MASS),
+        unit("s", 1.0, TIME),
+        unit("ms", 1e-3, TIME),
+        unit("us", 1e-6, TIME),
+        unit("ns", 1e-9, TIME),
+        unit("min", 60.0, TIME),
+        unit("h", 3600.0, TIME),
+        unit("day", 86400.0, TIME),
+        unit("days", 86400.0, TIME),
+        unit("week", 604800.0, TIME),
+        unit("weeks", 604800.0, TIME),
+        unit("A", 1.0, CURRENT),
+        unit("mA", 1e-3, CURRENT),
+        unit("K", 1.0, TEMPERATURE),
+        Unit { name: "C", factor: 1.0, offset: 273.15, dimension: TEMPERATURE },
+        Unit { name: "F", factor: 5.0 / 9.0, offset: 459.67 * 5.0 / 9.0, dimension: TEMPERATURE },
+        unit("mol", 1.0, AMOUNT),
+        unit("cd", 1.0, LUMINOSITY),
+        unit("B", 1.0, INFORMATION),
+        unit("bit", 0.125, INFORMATION),
+        unit("kB", 1e3, INFORMATION),
+        unit("KB", 1e3, INFORMATION),
+        unit("MB", 1e6, INFORMATION),
+        unit("GB", 1e9, INFORMATION),
+        unit("TB", 1e12, INFORMATION),
+        unit("KiB", 1024.0, INFORMATION),
+        unit("MiB", 1048576.0, INFORMATION),
+        unit("GiB", 1073741824.0, INFORMATION),
+        unit("TiB", 1099511627776.0, INFORMATION),
+        unit("L", 1e-3, VOLUME),
+        unit("mL", 1e-6, VOLUME),
+        unit("gal", 3.785_411_784e-3, VOLUME),
+        unit("Hz", 1.0, FREQUENCY),
+        unit("kHz", 1e3, FREQUENCY),
+        unit("MHz", 1e6, FREQUENCY),
+        unit("GHz", 1e9, FREQUENCY),
+        unit("mph", 0.44704, SPEED),
+        unit("N", 1.0, FORCE),
+        unit("kN", 1e3, FORCE),
+        unit("Pa", 1.0, PRESSURE),
+        unit("kPa", 1e3, PRESSURE),
+        unit("bar", 1e5, PRESSURE),
+        unit("atm", 101325.0, PRESSURE),
+        unit("psi", 6_894.757_293_168, PRESSURE),
+        unit("J", 1.0, ENERGY),
+        unit("kJ", 1e3, ENERGY),
+        unit("cal", 4.184, ENERGY),
+        unit("kcal", 4184.0, ENERGY),
+        unit("kWh", 3.6e6, ENERGY),
+        unit("eV", 1.602_176_634e-19, ENERGY),
+        unit("W", 1.0, POWER),
+        unit("kW", 1e3, POWER),
+        unit("hp", 745.699_871_582_270_2, POWER),
+        unit("V", 1.0, VOLTAGE),
+        unit("ohm", 1.0, RESISTANCE),
+    ];
+
+    pub fn lookup(name: &str) -> Option<&'static Unit> {
+        UNITS.iter().find(|unit| unit.name == name)
+    }
+
+    impl Unit {
+        /// `value` of this unit raised to `exponent`, in base units; the
+        /// offset of a temperature scale only makes sense unraised.
+        pub fn quantity(&self, value: f64, exponent: i32) -> Result<Quantity> {
+            if exponent == 1 {
+                return Ok(Quantity::new(value * self.factor + self.offset, self.dimension));
+            }
+            if self.offset != 0.0 {
+                return Err(CalcError::TypeMismatch("temperature scale in a compound unit"));
+            }
+            let unit = Quantity::new(self.factor, self.dimension).pow(exponent as f64)?;
+            Ok(Quantity::new(value * unit.value, unit.dimension))
+        }
+
+        /// The unit as a multiple of base units, `None` for temperature
+        /// scales with an offset.
+        pub fn scale(&self) -> Option<Quantity> {
+            (self.offset == 0.0).then_some(Quantity::new(self.factor, self.dimension))
+        }
+    }
+
+    /// A unit to convert to, like `km/h`, as the unit names with their
+    /// exponents.
+    #[derive(Debug, PartialEq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct Target(pub Vec<(String, i32)>);
+
+    impl Target {
+        /// The value of `quantity` in this unit, rounded to 12 significant
+        /// digits to hide the rounding errors of the conversion factors.
+        pub fn convert(&self, quantity: Quantity) -> Result<f64> {
+            let (value, dimension) = match self.0.as_slice() {
+                // a single unit may be a temperature scale
+                [(name, 1)] => {
+                    let unit = lookup(name).ok_or(CalcError::UnknownUnit)?;
+                    ((quantity.value - unit.offset) / unit.factor, unit.dimension)
+                },
+                units => {
+                    let mut target = Quantity::new(1.0, Dimension::NONE);
+                    for (name, exponent) in units {
+                        target = target.mul(lookup(name).ok_or(CalcError::UnknownUnit)?.quantity(1.0, *exponent)?);
+                    }
+                    (quantity.value / target.value, target.dimension)
+                },
+            };
+            if dimension != quantity.dimension {
+                return Err(CalcError::TypeMismatch("incompatible units"));
+            }
+            Ok(crate::math::significant(value))
+        }
+    }
+
+    impl fmt::Display for Target {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            for (i, (name, exponent)) in self.0.iter().enumerate() {
+                let (separator, exponent) = match (i, *exponent) {
+                    (0, exponent) => ("", exponent),
+                    (_, exponent) if exponent < 0 => ("/", - exponent),
+                    (_, exponent) => ("*", exponent),
+                };
+                match exponent {
+                    1 => write!(f, "{}{}", separator, name)?,
+                    _ => write!(f, "{}{}^{}", separator, name, exponent)?,
+                }
+            }
+            Ok(())
+        }
+    }
+
+    impl Dimension {
+        pub const NONE: Dimension = Dimension([0; 8]);
+
+        fn combine(self, other: Dimension, f: fn(i32, i32) -> i32) -> Dimension {
+            Dimension(std::array::from_fn(|i| f(self.0[i], other.0[i])))
+        }
+    }
+
+    impl Quantity {
+        pub fn new(value: f64, dimension: Dimension) -> Self {
+            Quantity { value, dimension }
+        }
+
+        /// Adds or subtracts quantities of the same dimension.
+        pub fn add(self, other: Quantity, f: fn(f64, f64) -> f64) -> Result<Quantity> {
+            if self.dimension != other.dimension {
+                return Err(CalcError::TypeMismatch("incompatible units"));
+            }
+            Ok(Quantity::new(f(self.value, other.value), self.dimension))
+        }
+
+        pub(crate) fn mul(self, other: Quantity) -> Quantity {
+            Quantity::new(self.value * other.value, self.dimension.combine(other.dimension, |a, b| a + b))
+        }
+
+        pub(crate) fn div(self, other: Quantity) -> Quantity {
+            Quantity::new(self.value / other.value, self.dimension.combine(other.dimension, |a, b| a - b))
+        }
+
+        /// Raises to a power that leaves whole exponents of the base units,
+        /// so `(m^2)^0.5` is `m` but `m^0.5` is rejected.
+        pub fn pow(self, exponent: f64) -> Result<Quantity> {
+            let mut exponents = [0; 8];
+            for (power, base) in exponents.iter_mut().zip(self.dimension.0) {
+                let product = base as f64 * exponent;
+                if product.fract() != 0.0 || product.abs() > i32::MAX as f64 {
+                    return Err(CalcError::TypeMismatch("fractional power of a unit"));
+                }
+                *power = product as i32;
+            }
+            Ok(Quantity::new(self.value.powf(exponent), Dimension(exponents)))
+        }
+    }
+
+    /// Writes base units like `kg*m/s^2`, or with negative exponents only,
+    /// like `s^-1`, when nothing is in the numerator.
+    impl fmt::Display for Dimension {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            let power = |name: &str, exponent: i32| match exponent {
+                1 => name.to_string(),
+                _ => format!("{}^{}", name, exponent),
+            };
+            let units = BASE_UNITS.iter().zip(self.0);
+            let numerator: Vec<_> = units.clone().filter(|(_, e)| *e > 0).map(|(name, e)| power(name, e)).collect();
+            let denominator: Vec<_> = units.clone().filter(|(_, e)| *e < 0).map(|(name, e)| power(name, - e)).collect();
+
+            match (numerator.len(), denominator.len()) {
+                (0, _) => {
+                    let inverse: Vec<_> = units.filter(|(_, e)| *e < 0).map(|(name, e)| power(name, e)).collect();
+                    write!(f, "{}", inverse.join("*"))
+                },
+                (_, 0) => write!(f, "{}", numerator.join("*")),
+                (_, 1) => write!(f, "{}/{}", numerator.join("*"), denominator[0]),
+                _ => write!(f, "{}/({})", numerator.join("*"), denominator.join("*")),
+            }
+        }
+    }
+}
+
+/// Calendar dates and durations written as clock times.
+pub mod time {
+    use std::fmt;
+
+    /// A day of the proleptic Gregorian calendar, counted from 1970-01-01.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct Date(pub i64);
+
+    /// A length of time in seconds, shown like a clock as `h:mm` or
+    /// `h:mm:ss`.
+    #[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct Duration(pub f64);
+
+    pub const SECONDS_PER_DAY: f64 = 86400.0;
+
+    impl Date {
+        /// `None` unless the day exists, so `2023-02-29` is rejected.
+        pub fn from_ymd(year: i64, month: i64, day: i64) -> Option<Date> {
+            if ! (1..=12).contains(&month) || ! (1..=31).contains(&day) {
+                return None;
+            }
+            let year = if month <= 2 { year - 1 } else { year };
+            let era = year.div_euclid(400);
+            let year_of_era = year - era * 400;
+            let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+            let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+            let date = Date(era * 146097 + day_of_era - 719468);
+            (date.ymd() == (if month <= 2 { year + 1 } else { year }, month, day)).then_some(date)
+        }
+
+        pub fn ymd(self) -> (i64, i64, i64) {
+            let days = self.0 + 719468;
+            let era = days.div_euclid(146097);
+            let day_of_era = days - era * 146097;
+            let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+            let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+            let shifted_month = (5 * day_of_year + 2) / 153;
+            let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+            let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+            let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+            (year, month, day)
+        }
+    }
+
+    impl fmt::Display for Date {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            let (year, month, day) = self.ymd();
+            write!(f, "{:04}-{:02}-{:02}", year, month, day)
+        }
+    }
+
+    impl fmt::Display for Duration {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            let sign = if self.0 < 0.0 { "-" } else { "" };
+            let total = self.0.abs();
+            let hours = (total / 3600.0).floor();
+            let minutes = ((total - hours * 3600.0) / 60.0).floor();
+            let seconds = total - hours * 3600.0 - minutes * 60.0;
+            write!(f, "{}{}:{:02}", sign, hours, minutes)?;
+            match seconds {
+                _ if seconds == 0.0 => Ok(()),
+                _ if seconds < 10.0 => write!(f, ":0{}", seconds),
+                _ => write!(f, ":{}", seconds),
+            }
+        }
+    }
+}
+
+pub mod ast {
+    use std::fmt;
+    use std::rc::Rc;
+
+    use crate::bigint::BigInt;
+    use crate::decimal::Decimal;
+    use crate::eval::{Context, Evaluator, Settings};
+    use crate::operators::{self, Associativity};
+    use crate::units::Target;
+    use crate::time::{Date, Duration};
+    use crate::value::Value;
+
+    /// An operator, one of the builtin or registered ones. Behind the alias
+    /// serde does not take it for a string borrowed from the input.
+    pub type Symbol = &'static str;
+
+    /// Trees compare equal when they have the same shape and literals, so
+    /// parsed inputs can be compared and diffed. With the `serde` feature
+    /// they can be serialized, to store or send parsed inputs.
+    #[derive(Debug, Clone, PartialEq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub enum Expr {
+        Number(f64),
+        /// a date literal like `2024-03-01`
+        Date(Date),
+        /// a clock time like `18:30`, the duration since midnight
+        Duration(Duration),
+        Integer(BigInt),
+        /// a literal read exactly, in decimal mode
+        Decimal(Decimal),
+        Variable(String),
+        /// prefix `+`, `-` and `!`
+        Unary(#[cfg_attr(feature = "serde", serde(with = "serialize::symbol"))] Symbol, Box<Expr>),
+        /// postfix `!` and `%`
+        Postfix(#[cfg_attr(feature = "serde", serde(with = "serialize::symbol"))] Symbol, Box<Expr>),
+        Binary(#[cfg_attr(feature = "serde", serde(with = "serialize::symbol"))] Symbol, Box<Expr>, Box<Expr>),
+        /// a function call like `sqrt(2)`
+        Call(String, Vec<Expr>),
+        /// a call of a function that is not named, like `f(1)(2)` or
+        /// `(x -> x + 1)(2)`
+        Apply(Box<Expr>, Vec<Expr>),
+        /// `[1, 2, 3]`
+        List(Vec<Expr>),
+        /// `list[index]`
+        Index(Box<Expr>, Box<Expr>),
+        /// `list[start:end]`, either bound may be left out
+        Slice(Box<Expr>, Option<Box<Expr>>, Option<Box<Expr>>),
+        /// `condition ? then : otherwise`
+        Conditional(Box<Expr>, Box<Expr>, Box<Expr>),
+        /// `(params) -> body`
+        Lambda(#[cfg_attr(feature = "serde", serde(with = "serialize::shared"))] Rc<Function>),
+        /// `let name = value in body`
+        Let(String, Box<Expr>, Box<Expr>),
+        /// a number with a unit and its power, like `3 m^2`
+        Quantity(Box<Expr>, String, i32),
+        /// `expr to unit`
+        Convert(Box<Expr>, #[cfg_attr(feature = "serde", serde(with = "serialize::shared"))] Rc<Target>),
+    }
+
+    /// A user-defined function, named or anonymous.
+    #[derive(Debug, Clone, PartialEq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct Function {
+        pub params: Vec<String>,
+        pub body: Expr,
+    }
+
+    /// A whole input line.
+    #[derive(Debug, Clone, PartialEq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub enum Statement {
+        Expr(Expr),
+        /// `name = expr`
+        Assign(String, Expr),
+        /// `name(params) = body`
+        Function(String, #[cfg_attr(feature = "serde", serde(with = "serialize::shared"))] Rc<Function>),
+    }
+
+    /// What serde cannot derive for the tree: operators are static strings,
+    /// and functions and units to convert to are shared.
+    #[cfg(feature = "serde")]
+    mod serialize {
+        pub mod symbol {
+            use std::cell::RefCell;
+
+            use serde::{Deserialize, Deserializer, Serializer};
+
+            use crate::ast::Symbol;
+            use crate::operators;
+
+            /// the operators besides the binary ones, prefix and postfix
+            const OTHERS: [&str; 4] = ["+", "-", "!", "%"];
+
+            thread_local! {
+                /// registered operators read so far; they are leaked to be
+                /// static like the symbols they were registered with, but
+                /// only once each
+                static REGISTERED: RefCell<Vec<&'static str>> = const { RefCell::new(Vec::new()) };
+            }
+
+            pub fn serialize<S: Serializer>(symbol: &Symbol, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.serialize_str(symbol)
+            }
+
+            pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Symbol, D::Error> {
+                let symbol = String::deserialize(deserializer)?;
+                let builtin = operators::BUILTIN.iter().flat_map(|(_, symbols, _)| symbols.iter()).chain(&OTHERS)
+                    .find(|builtin| **builtin == symbol);
+                if let Some(builtin) = builtin {
+                    return Ok(builtin);
+                }
+                Ok(REGISTERED.with_borrow_mut(|registered| match registered.iter().find(|registered| **registered == symbol) {
+                    Some(registered) => registered,
+                    None => {
+                        let leaked: Symbol = Box::leak(symbol.into_boxed_str());
+                        registered.push(leaked);
+                        leaked
+                    },
+                }))
+            }
+        }
+
+        pub mod shared {
+            use std::rc::Rc;
+
+            use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+            pub fn serialize<T: Serialize, S: Serializer>(value: &Rc<T>, serializer: S) -> Result<S::Ok, S::Error> {
+                T::serialize(value, serializer)
+            }
+
+            pub fn deserialize<'de, T: Deserialize<'de>, D: Deserializer<'de>>(deserializer: D) -> Result<Rc<T>, D::Error> {
+                T::deserialize(deserializer).map(Rc::new)
+            }
+        }
+    }
+
+    impl Expr {
+        /// Computes the parts made only of literals once, like the `2*pi`
+        /// of `2*pi*r`, as they would be with `settings`. Calls are left
+        /// alone, since a variable may hide the builtin of the same name.
+        pub fn fold_constants(&self, settings: &Settings) -> Expr {
+            let context = Context::new(settings.clone());
+            self.fold(&Evaluator::new(&context), settings)
+        }
+
+        fn is_constant(&self, settings: &Settings) -> bool {
+            match self {
+                Expr::Number(_) | Expr::Integer(_) | Expr::Decimal(_) => true,
+                Expr::Unary("+" | "-" | "!", oprand) | Expr::Postfix(_, oprand) => oprand.is_constant(settings),
+                Expr::Binary(operator, op1, op2) if *operator != "|>" && settings.operators.binary(operator).is_none() => {
+                    op1.is_constant(settings) && op2.is_constant(settings)
+                },
+                Expr::Conditional(condition, then, otherwise) => {
+                    [condition, then, otherwise].into_iter().all(|expr| expr.is_constant(settings))
+                },
+                _ => false,
+            }
+        }
+
+        /// The literal evaluating to `value` again, if there is one.
+        fn literal(value: Value, settings: &Settings) -> Option<Expr> {
+            match value {
+                // in decimal mode these literals evaluate to decimals
+                Value::Number(number) if ! settings.decimal => Some(Expr::Number(number)),
+                Value::Integer(integer) if ! settings.decimal => Some(Expr::Integer(integer)),
+                Value::Decimal(decimal) => Some(Expr::Decimal(decimal)),
+                _ => None,
+            }
+        }
+
+        fn fold(&self, evaluator: &Evaluator, settings: &Settings) -> Expr {
+            let literal = matches!(self, Expr::Number(_) | Expr::Integer(_) | Expr::Decimal(_));
+            if ! literal && self.is_constant(settings) {
+                // errors are left to be reported when it is evaluated
+                if let Some(folded) = evaluator.eval(self).ok().and_then(|value| Expr::literal(value, settings)) {
+                    return folded;
+                }
+            }
+            let fold = |expr: &Expr| Box::new(expr.fold(evaluator, settings));
+            match self {
+                Expr::Unary(operator, oprand) => Expr::Unary(operator, fold(oprand)),
+                Expr::Postfix(operator, oprand) => Expr::Postfix(operator, fold(oprand)),
+                // a percentage stays one, since `x + 10%` adds ten percent of `x`
+                Expr::Binary(operator, op1, op2) => match &**op2 {
+                    Expr::Postfix("%", percentage) => {
+                        Expr::Binary(operator, fold(op1), Box::new(Expr::Postfix("%", fold(percentage))))
+                    },
+                    _ => Expr::Binary(operator, fold(op1), fold(op2)),
+                },
+                Expr::Call(name, args) => Expr::Call(name.clone(), args.iter().map(|arg| *fold(arg)).collect()),
+                Expr::Apply(callee, args) => Expr::Apply(fold(callee), args.iter().map(|arg| *fold(arg)).collect()),
+                Expr::List(items) => Expr::List(items.iter().map(|item| *fold(item)).collect()),
+                Expr::Index(list, index) => Expr::Index(fold(list), fold(index)),
+                Expr::Slice(list, start, end) => {
+                    Expr::Slice(fold(list), start.as_deref().map(fold), end.as_deref().map(fold))
+                },
+                Expr::Conditional(condition, then, otherwise) => Expr::Conditional(fold(condition), fold(then), fold(otherwise)),
+                // constants cannot be bound, so folding under bindings is safe
+                Expr::Lambda(function) => Expr::Lambda(Rc::new(Function {
+                    params: function.params.clone(),
+                    body: *fold(&function.body),
+                })),
+                Expr::Let(name, value, body) => Expr::Let(name.clone(), fold(value), fold(body)),
+                Expr::Quantity(number, unit, exponent) => Expr::Quantity(fold(number), unit.clone(), *exponent),
+                Expr::Convert(expr, target) => Expr::Convert(fold(expr), target.clone()),
+                _ => self.clone(),
+            }
+        }
+
+        /// How tightly the expression binds when written out, to decide
+        /// where it needs brackets: binary operators bind as in
+        /// `operators::BUILTIN`, and registered ones, unknown here, looser
+        /// than all of them.
+        fn precedence(&self) -> usize {
+            match self {
+                Expr::Binary(operator, ..) => operators::builtin(operator).map_or(1, |(precedence, _)| precedence),
+                Expr::Number(number) if number.is_sign_negative() => UNARY,
+                Expr::Integer(integer) if integer.is_negative() => UNARY,
+                Expr::Decimal(decimal) if decimal.is_negative() => UNARY,
+                Expr::Unary(..) => UNARY,
+                Expr::Quantity(..) => QUANTITY,
+                Expr::Conditional(..) | Expr::Lambda(_) | Expr::Let(..) | Expr::Convert(..) => 0,
+                _ => ATOM,
+            }
+        }
+    }
+
+    /// a quantity like `3 m` binds tighter than `*` but not than `^`, which
+    /// would raise the unit
+    const QUANTITY: usize = 115;
+
+    /// prefix operators bind tighter than binary ones, and postfix ones and
+    /// atoms like calls tighter still
+    const UNARY: usize = 130;
+    const ATOM: usize = 140;
+
+    /// Writes the expression back as input, with only the brackets it
+    /// needs, like `2*x^2 + 1`; read again, it gives the same expression.
+    impl fmt::Display for Expr {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            let operand = |f: &mut fmt::Formatter, expr: &Expr, precedence: usize| {
+                if expr.precedence() < precedence { write!(f, "({})", expr) } else { write!(f, "{}", expr) }
+            };
+            match self {
+                Expr::Number(number) if number.is_nan() => write!(f, "nan"),
+                Expr::Number(number) => write!(f, "{:?}", number),
+                Expr::Integer(integer) => write!(f, "{}", integer),
+                Expr::Decimal(decimal) => write!(f, "{}", decimal),
+                Expr::Date(date) => write!(f, "{}", date),
+                Expr::Duration(duration) => write!(f, "{}", duration),
+                Expr::Variable(name) => write!(f, "{}", name),
+                // `-x^2` is `(-x)^2`
+                Expr::Unary(operator, oprand) => {
+                    write!(f, "{}", operator)?;
+                    operand(f, oprand, UNARY)
+                },
+                Expr::Postfix(operator, oprand) => {
+                    operand(f, oprand, ATOM)?;
+                    write!(f, "{}", operator)
+                },
+                Expr::Index(list, index) => {
+                    operand(f, list, ATOM)?;
+                    write!(f, "[{}]", index)
+                },
+                Expr::Slice(list, start, end) => {
+                    operand(f, list, ATOM)?;
+                    let bound = |bound: &Option<Box<Expr>>| bound.as_ref().map_or(String::new(), |bound| bound.to_string());
+                    write!(f, "[{}:{}]", bound(start), bound(end))
+                },
+                Expr::Binary(operator, op1, op2) => {
+                    let (left, right) = match operators::builtin(operator) {
+                        Some((precedence, Associativity::Right)) => (precedence + 1, precedence),
+                        // chained comparisons are read as `&&`, so `(a < b) < c`
+                        // keeps its brackets
+                        Some((precedence, Associativity::Chain)) => (precedence + 1, precedence + 1),
+                        Some((precedence, _)) => (precedence, precedence + 1),
+                        None => (UNARY, UNARY),
+                    };
+                    operand(f, op1, left)?;
+                    match *operator {
+                        "*" | "/" | "^" => write!(f, "{}", operator)?,
+                        _ => write!(f, " {} ", operator)?,
+                    }
+                    operand(f, op2, right)
+                },
+                Expr::Call(name, args) => {
+                    let args: Vec<_> = args.iter().map(Expr::to_string).collect();
+                    write!(f, "{}({})", name, args.join(", "))
+                },
+                Expr::Apply(callee, args) => {
+                    operand(f, callee, ATOM)?;
+                    let args: Vec<_> = args.iter().map(Expr::to_string).collect();
+                    write!(f, "({})", args.join(", "))
+                },
+                Expr::List(items) => {
+                    let items: Vec<_> = items.iter().map(Expr::to_string).collect();
+                    write!(f, "[{}]", items.join(", "))
+                },
+                Expr::Conditional(condition, then, otherwise) => {
+                    operand(f, condition, 1)?;
+                    write!(f, " ? ")?;
+                    operand(f, then, 1)?;
+                    write!(f, " : {}", otherwise)
+                },
+                Expr::Lambda(function) => write!(f, "({}) -> {}", function.params.join(", "), function.body),
+                Expr::Let(name, value, body) => write!(f, "let {} = {} in {}", name, value, body),
+                Expr::Quantity(number, unit, 1) => write!(f, "{} {}", number, unit),
+                Expr::Quantity(number, unit, exponent) => write!(f, "{} {}^{}", number, unit, exponent),
+                Expr::Convert(expr, target) => write!(f, "{} to {}", expr, target),
+            }
+        }
+    }
+
+    /// a fraction is set apart on its own, but would be unclear as the base
+    /// of a power
+    const FRACTION: usize = 135;
+
+    /// variables written as their Greek letters, like `\pi`
+    const GREEK: [&str; 23] = [
+        "alpha", "beta", "gamma", "delta", "epsilon", "zeta", "eta", "theta", "iota", "kappa", "lambda", "mu",
+        "nu", "xi", "pi", "rho", "sigma", "tau", "upsilon", "phi", "chi", "psi", "omega",
+    ];
+
+    /// builtins LaTeX has commands for
+    const LATEX_FUNCTIONS: [(&str, &str); 19] = [
+        ("sin", "\\sin"), ("cos", "\\cos"), ("tan", "\\tan"), ("asin", "\\arcsin"), ("acos", "\\arccos"),
+        ("atan", "\\arctan"), ("sinh", "\\sinh"), ("cosh", "\\cosh"), ("tanh", "\\tanh"), ("exp", "\\exp"),
+        ("ln", "\\ln"), ("log10", "\\log_{10}"), ("log2", "\\log_{2}"), ("min", "\\min"), ("max", "\\max"),
+        ("gcd", "\\gcd"), ("arg", "\\arg"), ("re", "\\Re"), ("im", "\\Im"),
+    ];
+
+    /// Escapes the characters LaTeX gives a meaning to, for `\text` and
+    /// the like.
+    fn latex_text(text: &str) -> String {
+        text.chars().map(|c| match c {
+            '\\' => "\\textbackslash{}".to_string(),
+            '~' => "\\textasciitilde{}".to_string(),
+            '^' => "\\textasciicircum{}".to_string(),
+            '_' | '%' | '&' | '#' | '$' | '{' | '}' => format!("\\{}", c),
+            c => c.to_string(),
+        }).collect()
+    }
+
+    /// A name as a variable: Greek ones as their letters, single letters
+    /// in italics as they are, and a trailing number as a subscript, like
+    /// `x_{1}` for `x1` or `x_1`.
+    fn latex_name(name: &str) -> String {
+        let base = name.trim_end_matches(|c: char| c.is_ascii_digit());
+        let index = &name[base.len()..];
+        let base = base.strip_suffix('_').unwrap_or(base);
+        match index {
+            index if ! index.is_empty() && ! base.is_empty() => format!("{}_{{{}}}", latex_name(base), index),
+            _ if GREEK.contains(&name) => format!("\\{}", name),
+            _ if name.chars().count() == 1 => name.to_string(),
+            _ => format!("\\mathit{{{}}}", latex_text(name)),
+        }
+    }
+
+    impl Expr {
+        /// Writes the expression as LaTeX math, with fractions, powers and
+        /// functions set as on paper, like `\frac{2 x^{2}}{3} + \sin\left(x\right)`.
+        pub fn to_latex(&self) -> String {
+            let operand = |expr: &Expr, precedence: usize| match expr.latex_precedence() < precedence {
+                true => format!("\\left({}\\right)", expr.to_latex()),
+                false => expr.to_latex(),
+            };
+            let list = |items: &[Expr]| items.iter().map(Expr::to_latex).collect::<Vec<_>>().join(", ");
+            match self {
+                Expr::Number(number) if number.is_nan() => "\\mathrm{NaN}".to_string(),
+                Expr::Number(number) if number.is_infinite() => {
+                    if *number < 0.0 { "-\\infty".to_string() } else { "\\infty".to_string() }
+                },
+                // the constants are read as their values
+                Expr::Number(number) if *number == std::f64::consts::PI => "\\pi".to_string(),
+                Expr::Number(number) if *number == std::f64::consts::E => "e".to_string(),
+                Expr::Number(number) => number.to_string(),
+                Expr::Integer(integer) => integer.to_string(),
+                Expr::Decimal(decimal) => decimal.to_string(),
+                Expr::Date(date) => format!("\\text{{{}}}", date),
+                Expr::Duration(duration) => format!("\\text{{{}}}", duration),
+                Expr::Variable(name) => latex_name(name),
+                Expr::Unary(operator, oprand) => match **oprand {
+                    Expr::Binary("*" | "/", ..) if *operator == "-" => format!("-{}", oprand.to_latex()),
+                    // `-\left(-x\right)` rather than `--x`
+                    _ => {
+                        let operator = if *operator == "!" { "\\lnot " } else { operator };
+                        format!("{}{}", operator, operand(oprand, UNARY + 1))
+                    },
+                },
+                Expr::Postfix("%", oprand) => format!("{}\\%", operand(oprand, ATOM)),
+                Expr::Postfix(operator, oprand) => format!("{}{}", operand(oprand, ATOM), operator),
+                Expr::Index(list, index) => format!("{}_{{{}}}", operand(list, ATOM), index.to_latex()),
+                Expr::Slice(list, start, end) => {
+                    let bound = |bound: &Option<Box<Expr>>| bound.as_ref().map_or(String::new(), |bound| bound.to_latex());
+                    format!("{}\\left[{}:{}\\right]", operand(list, ATOM), bound(start), bound(end))
+                },
+                Expr::Binary("/", op1, op2) => format!("\\frac{{{}}}{{{}}}", op1.to_latex(), op2.to_latex()),
+                Expr::Binary("//", op1, op2) => {
+                    format!("\\left\\lfloor \\frac{{{}}}{{{}}}\\right\\rfloor", op1.to_latex(), op2.to_latex())
+                },
+                // the exponent is set apart by its braces
+                Expr::Binary("^", op1, op2) => format!("{}^{{{}}}", operand(op1, ATOM), op2.to_latex()),
+                Expr::Binary(operator, op1, op2) => {
+                    let (left, right) = match operators::builtin(operator) {
+                        Some((precedence, Associativity::Chain)) => (precedence + 1, precedence + 1),
+                        Some((precedence, _)) => (precedence, precedence + 1),
+                        None => (UNARY, UNARY),
+                    };
+                    let symbol = match *operator {
+                        // a number before a variable or a call is multiplied
+                        // without a sign, like `2 x^{2}`
+                        "*" if op1.is_coefficient() && op2.is_juxtaposable() => "",
+                        "*" => "\\cdot ",
+                        "%" => "\\bmod ",
+                        "==" => "= ",
+                        "!=" => "\\ne ",
+                        "<=" => "\\le ",
+                        ">=" => "\\ge ",
+                        "&&" => "\\land ",
+                        "||" => "\\lor ",
+                        "xor" => "\\oplus ",
+                        "<<" => "\\ll ",
+                        ">>" => "\\gg ",
+                        "|>" => "\\triangleright ",
+                        "+" => "+ ",
+                        "-" => "- ",
+                        "<" => "< ",
+                        ">" => "> ",
+                        operator => return format!("{} \\mathbin{{\\text{{{}}}}} {}",
+                            operand(op1, left), latex_text(operator), operand(op2, right)),
+                    };
+                    format!("{} {}{}", operand(op1, left), symbol, operand(op2, right))
+                },
+                Expr::Call(name, args) => match (name.as_str(), args.as_slice()) {
+                    ("sqrt", [arg]) => format!("\\sqrt{{{}}}", arg.to_latex()),
+                    ("cbrt", [arg]) => format!("\\sqrt[3]{{{}}}", arg.to_latex()),
+                    ("abs", [arg]) => format!("\\left|{}\\right|", arg.to_latex()),
+                    ("floor", [arg]) => format!("\\left\\lfloor {}\\right\\rfloor", arg.to_latex()),
+                    ("ceil", [arg]) => format!("\\left\\lceil {}\\right\\rceil", arg.to_latex()),
+                    ("log", [base, arg]) => format!("\\log_{{{}}}\\left({}\\right)", base.to_latex(), arg.to_latex()),
+                    ("ncr", [n, k]) => format!("\\binom{{{}}}{{{}}}", n.to_latex(), k.to_latex()),
+                    (name, args) => {
+                        let function = match LATEX_FUNCTIONS.iter().find(|(function, _)| *function == name) {
+                            Some((_, command)) => command.to_string(),
+                            None if name.chars().count() == 1 => name.to_string(),
+                            None => format!("\\operatorname{{{}}}", latex_text(name)),
+                        };
+                        format!("{}\\left({}\\right)", function, list(args))
+                    },
+                },
+                Expr::Apply(callee, args) => format!("{}\\left({}\\right)", operand(callee, ATOM), list(args)),
+                Expr::List(items) => format!("\\left[{}\\right]", list(items)),
+                Expr::Conditional(condition, then, otherwise) => format!(
+                    "\\begin{{cases}} {} & \\text{{if }} {} \\\\ {} & \\text{{otherwise}} \\end{{cases}}",
+                    then.to_latex(), condition.to_latex(), otherwise.to_latex(),
+                ),
+                Expr::Lambda(function) => {
+                    let params: Vec<_> = function.params.iter().map(|param| latex_name(param)).collect();
+                    match params.as_slice() {
+                        [param] => format!("{} \\mapsto {}", param, function.body.to_latex()),
+                        params => format!("\\left({}\\right) \\mapsto {}", params.join(", "), function.body.to_latex()),
+                    }
+                },
+                Expr::Let(name, value, body) => {
+                    format!("\\text{{let }} {} = {} \\text{{ in }} {}", latex_name(name), value.to_latex(), body.to_latex())
+                },
+                Expr::Quantity(number, unit, 1) => format!("{}\\,\\mathrm{{{}}}", number.to_latex(), latex_text(unit)),
+                Expr::Quantity(number, unit, exponent) => {
+                    format!("{}\\,\\mathrm{{{}}}^{{{}}}", number.to_latex(), latex_text(unit), exponent)
+                },
+                Expr::Convert(expr, target) => {
+                    format!("{} \\to \\mathrm{{{}}}", expr.to_latex(), latex_text(&target.to_string()))
+                },
+            }
+        }
+
+        /// How tightly the expression binds when set in LaTeX, where
+        /// fractions and floors are bracketed by their own layout.
+        fn latex_precedence(&self) -> usize {
+            match self {
+                Expr::Binary("/", ..) => FRACTION,
+                Expr::Binary("//", ..) | Expr::Conditional(..) => ATOM,
+                _ => self.precedence(),
+            }
+        }
+
+        /// Whether the expression is a number read as the coefficient of
+        /// what follows it.
+        fn is_coefficient(&self) -> bool {
+            match self {
+                Expr::Number(number) => number.is_finite() && ! number.is_sign_negative(),
+                Expr::Integer(integer) => ! integer.is_negative(),
+                Expr::Decimal(decimal) => ! decimal.is_negative(),
+                _ => false,
+            }
+        }
+
+        /// Whether the expression can follow a coefficient without a sign.
+        fn is_juxtaposable(&self) -> bool {
+            match self {
+                Expr::Variable(_) | Expr::Call(..) => true,
+                Expr::Number(number) => *number == std::f64::consts::PI,
+                Expr::Binary("^", base, _) => matches!(**base, Expr::Variable(_) | Expr::Call(..)),
+                _ => false,
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::Lexer;
+        use crate::parser::Parser;
+
+        fn parse(input: &str) -> Vec<Statement> {
+            let mut lexer = Lexer::new(input);
+            Parser::new(&mut lexer, &Settings::default()).parse().unwrap_or_else(|error| panic!("{}: {:?}", input, error))
+        }
+
+        fn print(input: &str) -> String {
+            match parse(input).as_slice() {
+                [Statement::Expr(expr)] => expr.to_string(),
+                statements => panic!("not an expression: {:?}", statements),
+            }
+        }
+
+        #[test]
+        fn printed_expressions_parse_back() {
+            let inputs = [
+                "a || b && c", "(a || b) && c", "a && (b || c)", "a | b & c", "(a | b) & c", "a xor b | c",
+                "a << 1 + 2", "(a << 1) + 2", "a & 1 == 1", "(a & 1) == 1", "1 < 2 < 3", "(1 < 2) < 3", "1 < (2 < 3)",
+                "-x^2", "(-x)^2", "-(x^2)", "2^-3", "2^3^2", "(2^3)^2", "a - (b - c)", "a - b - c", "a + (b + c)",
+                "a * (b * c)", "a / (b / c)", "a / b / c", "!a * b", "!(a * b)", "-(a * b)", "-a * b", "(-3)!", "-3!",
+                "3!!", "10% + 5", "10% * 5", "x + 10%", "20% of 150", "x |> sqrt |> round", "(x |> sqrt) + 1", "1..5",
+                "(1..5)[2]", "[1, 2, 3][0]", "a ? b : c ? d : e", "(a ? b : c) ? d : e", "a ? (b ? c : d) : e",
+                "((x) -> x + 1)(2)", "f = (x, y) -> x * y", "let a = 2 in a * 3", "(let a = 2 in a) * 3", "2 * (3 m)",
+                "(3 m)^2", "3 m^2", "5 km to m", "(5 km to m) + 1", "0/0", "-inf", "1e300", "1e-20", "0.1 + 0.2",
+                "2024-03-01 + 3 days", "18:30 + 1", "a + b * c - d / e % f // g", "(a + b) * (c - d)", "2x",
+                "2(x + 1)", "√2", "√(x + 1)", "|x - 1|", "|x| + |y|", "a % b", "a % -b", "-(-x)", "- -x", "+x",
+                "x += 3", "f(x) = x < 2 ? x : f(x - 1)", "sum(k, 1, 10, k^2)", "x²", "-x²", "(a - b)²", "10 != 3 == 1",
+                "3.0 + x", "1e20 * x", "3.0^100", "2.5e-7", "-(a * b)", "-(x^2/y)", "(a + b) + (c + d)",
+            ];
+            for input in inputs {
+                for statement in parse(input) {
+                    let expr = match statement {
+                        Statement::Expr(expr) | Statement::Assign(_, expr) => expr,
+                        Statement::Function(_, function) => function.body.clone(),
+                    };
+                    let printed = expr.to_string();
+                    assert_eq!(parse(&printed), [Statement::Expr(expr)], "{} is printed as {}", input, printed);
+                }
+            }
+        }
+
+        #[test]
+        fn expressions_print_with_the_brackets_they_need() {
+            let printed = [
+                ("((2 * (x ^ 2))) + 1", "2*x^2 + 1"),
+                ("(a || b) && c", "(a || b) && c"),
+                ("a || (b && c)", "a || b && c"),
+                ("(1 < 2) < 3", "(1 < 2) < 3"),
+                ("a - (b - c)", "a - (b - c)"),
+                ("(2^3)^2", "(2^3)^2"),
+                ("2^(3^2)", "2^3^2"),
+                ("(3 m)^2", "(3 m)^2"),
+                ("!(a * b)", "!(a*b)"),
+                ("-(x^2)", "-(x^2)"),
+                ("3.0 + x", "3.0 + x"),
+                ("1e20 * x", "1e20*x"),
+                ("nan", "nan"),
+            ];
+            for (input, expected) in printed {
+                assert_eq!(print(input), expected, "{}", input);
+            }
+        }
+
+        fn latex(input: &str) -> String {
+            match parse(input).as_slice() {
+                [Statement::Expr(expr)] => expr.to_latex(),
+                statements => panic!("not an expression: {:?}", statements),
+            }
+        }
+
+        #[test]
+        fn expressions_render_as_latex() {
+            let rendered = [
+                ("x_1", "x_{1}"),
+                ("x1 + alpha2", "x_{1} + \\alpha_{2}"),
+                ("x_max + _1", "\\mathit{x\\_max} + \\mathit{\\_1}"),
+                ("log(2, x)", "\\log_{2}\\left(x\\right)"),
+                ("2*x^2/3 + sin(x)", "\\frac{2 x^{2}}{3} + \\sin\\left(x\\right)"),
+                ("sqrt(x + 1) * cbrt(8)", "\\sqrt{x + 1} \\cdot \\sqrt[3]{8}"),
+                ("(1/2)^2 + 2^(1/2)", "\\left(\\frac{1}{2}\\right)^{2} + 2^{\\frac{1}{2}}"),
+                ("|x - 1| + 7 // 2", "\\left|x - 1\\right| + \\left\\lfloor \\frac{7}{2}\\right\\rfloor"),
+                ("ncr(n, k) * n!", "\\binom{n}{k} \\cdot n!"),
+                ("a <= b && b != c", "a \\le b \\land b \\ne c"),
+                ("2*pi*r", "2 \\pi \\cdot r"),
+                ("-(-x)", "-\\left(-x\\right)"),
+                ("x > 0 ? x : -x", "\\begin{cases} x & \\text{if } x > 0 \\\\ -x & \\text{otherwise} \\end{cases}"),
+                ("3 m^2", "3\\,\\mathrm{m}^{2}"),
+                ("5 km to m", "5\\,\\mathrm{km} \\to \\mathrm{m}"),
+                ("x + 10%", "x + 10\\%"),
+            ];
+            for (input, expected) in rendered {
+                assert_eq!(latex(input), expected, "{}", input);
+            }
+        }
+    }
+}
+
+/// Algebra on expressions with unknowns, which brings sums and products
+/// into a normal form: numbers are folded and like terms combined, so
+/// `2*x + 3*x` is `5*x`.
+pub mod symbolic {
+    use crate::CalcError;
+    use crate::ast::Expr;
+    use crate::bigint::BigInt;
+
+    /// A number times powers of factors, which are variables or
+    /// expressions that are not taken apart, like `sin(x)`.
+    #[derive(Debug, Clone)]
+    struct Term {
+        coefficient: f64,
+        /// variables and then other factors, each in the order of their
+        /// written forms, which are kept to tell like factors
+        factors: Vec<(String, Expr, f64)>,
+    }
+
+    /// A sum of unlike terms, empty for zero.
+    type Sum = Vec<Term>;
+
+    impl Term {
+        fn constant(coefficient: f64) -> Term {
+            Term { coefficient, factors: Vec::new() }
+        }
+
+        fn factor(expr: Expr, exponent: f64) -> Term {
+            Term { coefficient: 1.0, factors: vec![(expr.to_string(), expr, exponent)] }
+        }
+
+        fn mul(&self, other: &Term) -> Term {
+            let mut factors = self.factors.clone();
+            for (key, expr, exponent) in &other.factors {
+                match factors.iter().position(|(other, ..)| other == key) {
+                    Some(i) => factors[i].2 += exponent,
+                    None => factors.push((key.clone(), expr.clone(), *exponent)),
+                }
+            }
+            factors.retain(|(.., exponent)| *exponent != 0.0);
+            factors.sort_by_key(|(key, expr, _)| (! matches!(expr, Expr::Variable(_)), key.clone()));
+            Term { coefficient: self.coefficient * other.coefficient, factors }
+        }
+
+        /// `None` where the power has no real value to fold, like that of
+        /// a negative coefficient to a fractional exponent.
+        fn pow(&self, exponent: f64) -> Option<Term> {
+            if exponent.fract() != 0.0 && self.coefficient < 0.0 {
+                return None;
+            }
+            let factors = self.factors.iter()
+                .map(|(key, expr, power)| (key.clone(), expr.clone(), power * exponent))
+                .collect();
+            Some(Term { coefficient: self.coefficient.powf(exponent), factors })
+        }
+
+        fn is_like(&self, other: &Term) -> bool {
+            self.factors.len() == other.factors.len() && self.factors.iter().zip(&other.factors)
+                .all(|((key1, _, exponent1), (key2, _, exponent2))| key1 == key2 && exponent1 == exponent2)
+        }
+
+        fn degree(&self) -> f64 {
+            self.factors.iter().map(|(.., exponent)| exponent).sum()
+        }
+
+        /// Writes the term without its sign, which the sum takes care of.
+        fn to_expr(&self) -> Expr {
+            let power = |expr: &Expr, exponent: f64| match exponent {
+                _ if exponent == 1.0 => expr.clone(),
+                _ => Expr::Binary("^", Box::new(expr.clone()), Box::new(number(exponent))),
+            };
+            let product = |factors: Vec<Expr>| factors.into_iter()
+                .reduce(|product, factor| Expr::Binary("*", Box::new(product), Box::new(factor)));
+            // the coefficient is the first factor, so `2*x*y` groups like it
+            // reads
+            let coefficient = (self.coefficient.abs() != 1.0).then(|| number(self.coefficient.abs()));
+            let numerator = product(coefficient.into_iter()
+                .chain(self.factors.iter().filter(|(.., e)| *e > 0.0).map(|(_, expr, e)| power(expr, *e))).collect());
+            let numerator = numerator.unwrap_or_else(|| number(self.coefficient.abs()));
+            let denominator = product(self.factors.iter().filter(|(.., e)| *e < 0.0).map(|(_, expr, e)| power(expr, - e)).collect());
+            match denominator {
+                Some(denominator) => Expr::Binary("/", Box::new(numerator), Box::new(denominator)),
+                None => numerator,
+            }
+        }
+    }
+
+    /// The literal of a number in a result, an integer where it is exactly
+    /// one, so results read `3*x^2` rather than `3.0*x^2.0`.
+    fn number(n: f64) -> Expr {
+        match BigInt::from_f64(n) {
+            Some(integer) if n.abs() < 2f64.powi(53) => Expr::Integer(integer),
+            _ => Expr::Number(n),
+        }
+    }
+
+    fn add(mut sum: Sum, other: Sum) -> Sum {
+        for term in other {
+            match sum.iter_mut().find(|like| like.is_like(&term)) {
+                Some(like) => like.coefficient += term.coefficient,
+                None => sum.push(term),
+            }
+        }
+        sum.retain(|term| term.coefficient != 0.0);
+        sum
+    }
+
+    fn mul(a: &Sum, b: &Sum) -> Sum {
+        a.iter().flat_map(|x| b.iter().map(move |y| x.mul(y)))
+            .fold(Vec::new(), |sum, term| add(sum, vec![term]))
+    }
+
+    fn scale(sum: Sum, factor: f64) -> Sum {
+        mul(&sum, &vec![Term::constant(factor)])
+    }
+
+    /// The number a sum amounts to, if it has no factors.
+    fn constant(sum: &Sum) -> Option<f64> {
+        match sum.as_slice() {
+            [] => Some(0.0),
+            [term] if term.factors.is_empty() => Some(term.coefficient),
+            _ => None,
+        }
+    }
+
+    fn to_sum(expr: &Expr) -> Sum {
+        match expr {
+            Expr::Number(number) => add(Vec::new(), vec![Term::constant(*number)]),
+            Expr::Integer(integer) => to_sum(&Expr::Number(integer.to_f64())),
+            Expr::Decimal(decimal) => to_sum(&Expr::Number(decimal.to_f64())),
+            Expr::Unary("-", oprand) => scale(to_sum(oprand), -1.0),
+            Expr::Unary("+", oprand) => to_sum(oprand),
+            Expr::Binary("+", op1, op2) => add(to_sum(op1), to_sum(op2)),
+            Expr::Binary("-", op1, op2) => add(to_sum(op1), scale(to_sum(op2), -1.0)),
+            Expr::Binary("*", op1, op2) => mul(&to_sum(op1), &to_sum(op2)),
+            Expr::Binary("/", op1, op2) => {
+                let divisor = to_sum(op2);
+                let inverse = match divisor.as_slice() {
+                    [term] => term.pow(-1.0).unwrap(),
+                    _ => Term::factor(from_sum(divisor), -1.0),
+                };
+                mul(&to_sum(op1), &vec![inverse])
+            },
+            Expr::Binary("^", base, exponent) => {
+                let (base, exponent) = (to_sum(base), to_sum(exponent));
+                match (base.as_slice(), constant(&exponent)) {
+                    ([], Some(exponent)) => to_sum(&Expr::Number(0f64.powf(exponent))),
+                    ([term], Some(exponent)) if term.pow(exponent).is_some() => vec![term.pow(exponent).unwrap()],
+                    (_, Some(exponent)) => vec![Term::factor(from_sum(base), exponent)],
+                    _ => vec![Term::factor(Expr::Binary("^", Box::new(from_sum(base)), Box::new(from_sum(exponent))), 1.0)],
+                }
+            },
+            Expr::Call(name, args) => vec![Term::factor(Expr::Call(name.clone(), args.iter().map(simplify).collect()), 1.0)],
+            _ => vec![Term::factor(expr.clone(), 1.0)],
+        }
+    }
+
+    /// Writes terms of higher degree first, like `x^2 + 2*x + 1`.
+    fn from_sum(mut sum: Sum) -> Expr {
+        let order = |term: &Term| term.factors.iter().map(|(key, expr, _)| (! matches!(expr, Expr::Variable(_)), key.clone()))
+            .collect::<Vec<_>>();
+        sum.sort_by(|a, b| b.degree().total_cmp(&a.degree()).then_with(|| order(a).cmp(&order(b))));
+        let mut terms = sum.iter();
+        // the sign goes to the first factor, like `-2*x`
+        fn negate(expr: Expr) -> Expr {
+            match expr {
+                Expr::Binary(operator @ ("*" | "/"), op1, op2) => Expr::Binary(operator, Box::new(negate(*op1)), op2),
+                expr => Expr::Unary("-", Box::new(expr)),
+            }
+        }
+        let first = match terms.next() {
+            Some(first) if first.coefficient < 0.0 => negate(first.to_expr()),
+            Some(first) => first.to_expr(),
+            None => return number(0.0),
+        };
+        terms.fold(first, |sum, term| {
+            let operator = if term.coefficient < 0.0 { "-" } else { "+" };
+            Expr::Binary(operator, Box::new(sum), Box::new(term.to_expr()))
+        })
+    }
+
+    /// Brings the arithmetic of an expression into normal form; other
+    /// operations are left as they are.
+    pub fn simplify(expr: &Expr) -> Expr {
+        from_sum(to_sum(expr))
+    }
+
+    /// The coefficients of `expr` as a polynomial in `name`, constant
+    /// first, or `None` if it is not one.
+    pub fn polynomial(expr: &Expr, name: &str) -> Option<Vec<f64>> {
+        let mut coefficients = Vec::new();
+        for term in to_sum(expr) {
+            let power = match term.factors.as_slice() {
+                [] => 0,
+                [(_, Expr::Variable(variable), exponent)] if variable == name && exponent.fract() == 0.0 && (1.0..=64.0).contains(exponent) => {
+                    *exponent as usize
+                },
+                _ => return None,
+            };
+            if coefficients.len() <= power {
+                coefficients.resize(power + 1, 0.0);
+            }
+            coefficients[power] += term.coefficient;
+        }
+        Some(coefficients)
+    }
+
+    pub fn depends_on(expr: &Expr, name: &str) -> bool {
+        match expr {
+            Expr::Variable(variable) => variable == name,
+            Expr::Unary(_, oprand) | Expr::Postfix(_, oprand) => depends_on(oprand, name),
+            Expr::Binary(_, op1, op2) => depends_on(op1, name) || depends_on(op2, name),
+            Expr::Call(_, args) | Expr::List(args) => args.iter().any(|arg| depends_on(arg, name)),
+            Expr::Apply(callee, args) => depends_on(callee, name) || args.iter().any(|arg| depends_on(arg, name)),
+            _ => false,
+        }
+    }
+
+    /// The variables `expr` refers to, in the order they first appear.
+    pub fn variables(expr: &Expr) -> Vec<String> {
+        fn collect(expr: &Expr, variables: &mut Vec<String>) {
+            match expr {
+                Expr::Variable(name) if ! variables.contains(name) => variables.push(name.clone()),
+                Expr::Unary(_, oprand) | Expr::Postfix(_, oprand) => collect(oprand, variables),
+                Expr::Binary(_, op1, op2) | Expr::Index(op1, op2) => {
+                    collect(op1, variables);
+                    collect(op2, variables);
+                },
+                Expr::Call(_, args) | Expr::List(args) => args.iter().for_each(|arg| collect(arg, variables)),
+                Expr::Apply(callee, args) => {
+                    collect(callee, variables);
+                    args.iter().for_each(|arg| collect(arg, variables));
+                },
+                Expr::Conditional(condition, then, otherwise) => {
+                    [condition, then, otherwise].into_iter().for_each(|expr| collect(expr, variables));
+                },
+                _ => (),
+            }
+        }
+        let mut variables = Vec::new();
+        collect(expr, &mut variables);
+        variables
+    }
+
+    /// The derivative of `expr` by the variable `name`, not yet simplified.
+    pub fn derivative(expr: &Expr, name: &str) -> Result<Expr, CalcError> {
+        let boxed = Box::new;
+        let number = |n: f64| boxed(number(n));
+        let call = |function: &str, arg: &Expr| boxed(Expr::Call(function.to_string(), vec![arg.clone()]));
+        if ! depends_on(expr, name) {
+            return Ok(*number(0.0));
+        }
+        Ok(match expr {
+            Expr::Variable(_) => *number(1.0),
+            Expr::Unary(operator @ ("+" | "-"), oprand) => Expr::Unary(operator, boxed(derivative(oprand, name)?)),
+            Expr::Binary(operator @ ("+" | "-"), op1, op2) => {
+                Expr::Binary(operator, boxed(derivative(op1, name)?), boxed(derivative(op2, name)?))
+            },
+            Expr::Binary("*", op1, op2) => Expr::Binary(
+                "+",
+                boxed(Expr::Binary("*", boxed(derivative(op1, name)?), op2.clone())),
+                boxed(Expr::Binary("*", op1.clone(), boxed(derivative(op2, name)?))),
+            ),
+            Expr::Binary("/", op1, op2) => Expr::Binary(
+                "/",
+                boxed(Expr::Binary(
+                    "-",
+                    boxed(Expr::Binary("*", boxed(derivative(op1, name)?), op2.clone())),
+                    boxed(Expr::Binary("*", op1.clone(), boxed(derivative(op2, name)?))),
+                )),
+                boxed(Expr::Binary("^", op2.clone(), number(2.0))),
+            ),
+            // the power rule where the exponent is constant, otherwise
+            // `(a^b)' = a^b * (b' * ln(a) + b * a' / a)`
+            Expr::Binary("^", base, exponent) if ! depends_on(exponent, name) => Expr::Binary(
+                "*",
+                boxed(Expr::Binary(
+                    "*",
+                    exponent.clone(),
+                    boxed(Expr::Binary("^", base.clone(), boxed(Expr::Binary("-", exponent.clone(), number(1.0))))),
+                )),
+                boxed(derivative(base, name)?),
+            ),
+            Expr::Binary("^", base, exponent) => Expr::Binary(
+                "*",
+                boxed(expr.clone()),
+                boxed(Expr::Binary(
+                    "+",
+                    boxed(Expr::Binary("*", boxed(derivative(exponent, name)?), call("ln", base))),
+                    boxed(Expr::Binary(
+                        "/",
+                        boxed(Expr::Binary("*", exponent.clone(), boxed(derivative(base, name)?))),
+                        base.clone(),
+                    )),
+                )),
+            ),
+            // the chain rule
+            Expr::Call(function, args) if args.len() == 1 => {
+                let arg = &args[0];
+                let outer = match function.as_str() {
+                    "sin" => *call("cos", arg),
+                    "cos" => Expr::Unary("-", call("sin", arg)),
+                    "tan" => Expr::Binary("/", number(1.0), boxed(Expr::Binary("^", call("cos", arg), number(2.0)))),
+                    "exp" => *call("exp", arg),
+                    "ln" => Expr::Binary("/", number(1.0), boxed(arg.clone())),
+                    "log10" => Expr::Binary("/", number(1.0), boxed(Expr::Binary("*", boxed(arg.clone()), number(10f64.ln())))),
+                    "log2" => Expr::Binary("/", number(1.0), boxed(Expr::Binary("*", boxed(arg.clone()), number(2f64.ln())))),
+                    "sqrt" => Expr::Binary("/", number(1.0), boxed(Expr::Binary("*", number(2.0), call("sqrt", arg)))),
+                    "cbrt" => Expr::Binary("/", number(1.0), boxed(Expr::Binary("*", number(3.0), boxed(Expr::Binary("^", call("cbrt", arg), number(2.0)))))),
+                    "abs" => Expr::Binary("/", boxed(arg.clone()), call("abs", arg)),
+                    _ => return Err(CalcError::InvalidArguments("cannot differentiate this function")),
+                };
+                Expr::Binary("*", boxed(outer), boxed(derivative(arg, name)?))
+            },
+            _ => return Err(CalcError::InvalidArguments("cannot differentiate this expression")),
+        })
+    }
+}
+
+/// Time value of money, with the sign convention of spreadsheets: money
+/// received is positive and money paid out negative, so a loan `pv` is
+/// repaid by a negative `pmt`. Payments are at the end of each period
+/// unless `due` is 1, for the beginning.
+pub mod finance {
+    /// The growth of one unit over `nper` periods, and the factor of the
+    /// payments in the future value.
+    fn factors(rate: f64, nper: f64, due: f64) -> (f64, f64) {
+        if rate == 0.0 {
+            return (1.0, nper);
+        }
+        let growth = (1.0 + rate).powf(nper);
+        (growth, (1.0 + rate * due) * (growth - 1.0) / rate)
+    }
+
+    /// The payment per period that turns present value `pv` into future
+    /// value `fv`.
+    pub fn pmt(rate: f64, nper: f64, pv: f64, fv: f64, due: f64) -> f64 {
+        let (growth, annuity) = factors(rate, nper, due);
+        -(pv * growth + fv) / annuity
+    }
+
+    pub fn fv(rate: f64, nper: f64, pmt: f64, pv: f64, due: f64) -> f64 {
+        let (growth, annuity) = factors(rate, nper, due);
+        -(pv * growth + pmt * annuity)
+    }
+
+    /// `principal` after `years` at the nominal annual `rate`, compounded
+    /// `periods` times a year.
+    pub fn compound(principal: f64, rate: f64, periods: f64, years: f64) -> f64 {
+        principal * (1.0 + rate / periods).powf(periods * years)
+    }
+
+    /// The limit of `compound` as the periods grow without bound.
+    pub fn compound_continuously(principal: f64, rate: f64, years: f64) -> f64 {
+        principal * (rate * years).exp()
+    }
+
+    /// The net present value of cash flows one period apart, the first of
+    /// which is now and not discounted.
+    pub fn npv(rate: f64, flows: &[f64]) -> f64 {
+        flows.iter().rev().fold(0.0, |value, flow| value / (1.0 + rate) + flow)
+    }
+
+    /// The rate at which the net present value of `flows` is zero, by
+    /// Newton's method from 10%, or else by bisecting the first change of
+    /// sign between -99% and 1000%.
+    pub fn irr(flows: &[f64]) -> Option<f64> {
+        const TOLERANCE: f64 = 1e-12;
+        let derivative = |rate: f64| {
+            flows.iter().enumerate().skip(1).map(|(t, flow)| -(t as f64) * flow / (1.0 + rate).powi(t as i32 + 1)).sum::<f64>()
+        };
+
+        let mut rate = 0.1;
+        for _ in 0..100 {
+            let step = npv(rate, flows) / derivative(rate);
+            rate -= step;
+            if ! rate.is_finite() || rate <= -1.0 {
+                break;
+            }
+            if step.abs() <= TOLERANCE * (1.0 + rate.abs()) {
+                return Some(rate);
+            }
+        }
+
+        let rates = (0..=10_000).map(|i| -0.99 + i as f64 * 10.99 / 10_000.0);
+        let (mut a, mut b) = rates.clone().zip(rates.skip(1))
+            .find(|(a, b)| (npv(*a, flows) < 0.0) != (npv(*b, flows) < 0.0))?;
+        let negative = npv(a, flows) < 0.0;
+        for _ in 0..200 {
+            let middle = (a + b) / 2.0;
+            if (npv(middle, flows) < 0.0) == negative { a = middle } else { b = middle }
+        }
+        Some(a)
+    }
+}
+
+/// Primality and factorization of 64-bit integers.
+pub mod primes {
+    fn mul_mod(a: u64, b: u64, m: u64) -> u64 {
+        (a as u128 * b as u128 % m as u128) as u64
+    }
+
+    fn pow_mod(mut base: u64, mut exponent: u64, m: u64) -> u64 {
+        let mut result = 1 % m;
+        base %= m;
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                result = mul_mod(result, base, m);
+            }
+            base = mul_mod(base, base, m);
+            exponent >>= 1;
+        }
+        result
+    }
+
+    /// Miller-Rabin with the first twelve primes as bases, which is
+    /// deterministic for every 64-bit `n`.
+    pub fn is_prime(n: u64) -> bool {
+        const BASES: [u64; 12] = [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37];
+        if n < 2 {
+            return false;
+        }
+        if let Some(base) = BASES.iter().find(|base| n.is_multiple_of(**base)) {
+            return n == *base;
+        }
+        let (mut d, mut s) = (n - 1, 0);
+        while d % 2 == 0 {
+            d /= 2;
+            s += 1;
+        }
+        BASES.iter().all(|base| {
+            let mut x = pow_mod(*base, d, n);
+            if x == 1 || x == n - 1 {
+                return true;
+            }
+            (1..s).any(|_| {
+                x = mul_mod(x, x, n);
+                x == n - 1
+            })
+        })
+    }
+
+    /// The smallest prime greater than `n`, unless it does not fit.
+    pub fn next_prime(n: u64) -> Option<u64> {
+        (n.checked_add(1)?..=u64::MAX).find(|candidate| is_prime(*candidate))
+    }
+
+    fn gcd(a: u64, b: u64) -> u64 {
+        if b == 0 { a } else { gcd(b, a % b) }
+    }
+
+    /// A non-trivial divisor of the composite `n`, by Pollard's rho with
+    /// Floyd's cycle detection, trying other polynomials when one fails.
+    fn divisor(n: u64) -> u64 {
+        (1..).find_map(|c| {
+            let f = |x| ((mul_mod(x, x, n) as u128 + c as u128) % n as u128) as u64;
+            let (mut x, mut y, mut d) = (2, 2, 1);
+            while d == 1 {
+                x = f(x);
+                y = f(f(y));
+                d = gcd(x.abs_diff(y), n);
+            }
+            (d != n).then_some(d)
+        }).unwrap()
+    }
+
+    /// The prime factors of `n` in ascending order, repeated by
+    /// multiplicity.
+    pub fn factor(mut n: u64) -> Vec<u64> {
+        let mut factors = Vec::new();
+        for p in [2, 3, 5] {
+            while n.is_multiple_of(p) && n > 1 {
+                factors.push(p);
+                n /= p;
+            }
+        }
+        let mut pending = vec![n];
+        while let Some(n) = pending.pop() {
+            if n == 1 {
+                continue;
+            }
+            if is_prime(n) {
+                factors.push(n);
+            } else {
+                let d = divisor(n);
+                pending.extend([d, n / d]);
+            }
+        }
+        factors.sort_unstable();
+        factors
+    }
+}
+
+/// A splitmix64 generator, seeded from the clock until `seed(n)` is called.
+pub mod random {
+    use std::cell::Cell;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    /// A splitmix64 generator. Every context has its own, so seeding one
+    /// calculator leaves the numbers of another alone.
+    #[derive(Debug, Clone)]
+    pub struct Generator {
+        state: Cell<u64>,
+    }
+
+    impl Generator {
+        pub fn new(seed: u64) -> Self {
+            Generator { state: Cell::new(seed) }
+        }
+
+        pub fn seed(&self, seed: u64) {
+            self.state.set(seed);
+        }
+
+        pub fn next_u64(&self) -> u64 {
+            let next = self.state.get().wrapping_add(0x9e37_79b9_7f4a_7c15);
+            self.state.set(next);
+            let z = (next ^ (next >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+            let z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+            z ^ (z >> 31)
+        }
+
+        /// uniformly distributed in `[0, 1)`
+        pub fn uniform(&self) -> f64 {
+            (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+        }
+
+        /// standard normal distribution, by the Box-Muller transform
+        pub fn normal(&self) -> f64 {
+            let u = 1.0 - self.uniform();
+            let v = self.uniform();
+            (-2.0 * u.ln()).sqrt() * (std::f64::consts::TAU * v).cos()
+        }
+    }
+
+    /// seeded from the clock
+    impl Default for Generator {
+        fn default() -> Self {
+            Generator::new(SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_nanos() as u64))
+        }
+    }
+}
+
+pub mod functions {
+    use crate::{finance, math, primes, CalcError};
+    use crate::ast::{Expr, Function};
+    use crate::bigint::{BigInt, MAX_BITS};
+    use crate::complex::Complex;
+    use crate::decimal::Decimal;
+    use crate::eval::{self, Remainder, Settings};
+    use crate::random::Generator;
+    use crate::value::{Closure, Value};
+    use std::ops::RangeInclusive;
+    use std::rc::Rc;
+
+    use Call::{Random, Real, Values};
+
+    type Result<T> = std::result::Result<T, CalcError>;
+
+    #[derive(Debug)]
+    pub struct Builtin {
+        pub name: &'static str,
+        /// accepted numbers of arguments
+        pub arity: RangeInclusive<usize>,
+        pub call: Call,
+    }
+
+    #[derive(Debug)]
+    pub enum Call {
+        /// a function of real numbers, applied to each element of a list
+        /// argument, or given the elements if it takes any number of them
+        Real(fn(&[f64], &Settings) -> Result<f64>),
+        /// a function of any values, like `len` of a list
+        Values(fn(&[Value], &Settings) -> Result<Value>),
+        /// a function of real numbers drawing from the random numbers of
+        /// the context, like `rand`
+        Random(fn(&[f64], &Generator) -> Result<f64>),
+    }
+
+    impl Builtin {
+        pub fn is_variadic(&self) -> bool {
+            *self.arity.end() == VARIADIC
+        }
+    }
+
+    fn sqrt(args: &[f64]) -> Result<f64> {
+        if args[0] < 0.0 {
+            Err(CalcError::Domain("square root of a negative number"))
+        } else {
+            Ok(args[0].sqrt())
+        }
+    }
+
+    /// Applies a logarithm `f` after checking that `x` is in its domain.
+    fn logarithm(x: f64, f: fn(f64) -> f64) -> Result<f64> {
+        if x <= 0.0 {
+            Err(CalcError::Domain("logarithm of a non-positive number"))
+        } else {
+            Ok(f(x))
+        }
+    }
+
+    /// Applies an inverse function `f` after checking that `x` is in the
+    /// range of the function it inverts.
+    fn inverse(x: f64, domain: RangeInclusive<f64>, f: fn(f64) -> f64) -> Result<f64> {
+        if domain.contains(&x) {
+            Ok(f(x))
+        } else {
+            Err(CalcError::Domain("argument out of the domain of the inverse function"))
+        }
+    }
+
+    fn atanh(x: f64) -> Result<f64> {
+        if x.abs() < 1.0 {
+            Ok(x.atanh())
+        } else {
+            Err(CalcError::Domain("argument out of the domain of the inverse function"))
+        }
+    }
+
+    fn is_pole(x: f64) -> bool {
+        x <= 0.0 && x.fract() == 0.0
+    }
+
+    fn gamma(x: f64) -> Result<f64> {
+        if is_pole(x) {
+            return Err(CalcError::Domain("gamma of a non-positive integer"));
+        }
+        // exactly `(x - 1)!` for positive integers
+        let result = if x.fract() == 0.0 && x <= 171.0 { falling_factorial(x as u64 - 1, x as u64 - 1)? } else { math::gamma(x) };
+        if result.is_finite() { Ok(result) } else { Err(CalcError::Overflow("result overflows")) }
+    }
+
+    fn lgamma(x: f64) -> Result<f64> {
+        if is_pole(x) {
+            return Err(CalcError::Domain("gamma of a non-positive integer"));
+        }
+        Ok(math::ln_gamma(x))
+    }
+
+    /// `gamma(a) gamma(b) / gamma(a + b)`, through logarithms when large
+    /// arguments would overflow on the way.
+    fn beta(args: &[f64]) -> Result<f64> {
+        let (a, b) = (args[0], args[1]);
+        if is_pole(a) || is_pole(b) {
+            return Err(CalcError::Domain("gamma of a non-positive integer"));
+        }
+        if is_pole(a + b) {
+            return Ok(0.0);
+        }
+        if let (Ok(ga), Ok(gb), Ok(gab)) = (gamma(a), gamma(b), gamma(a + b)) {
+            let direct = ga * gb / gab;
+            if direct.is_normal() {
+                return Ok(direct);
+            }
+        }
+        // gamma is negative between odd and even negative integers
+        let sign = [a, b, a + b].iter().map(|x| if *x < 0.0 && x.floor() % 2.0 != 0.0 { -1.0 } else { 1.0 }).product::<f64>();
+        Ok(sign * (math::ln_gamma(a) + math::ln_gamma(b) - math::ln_gamma(a + b)).exp())
+    }
+
+    fn log(args: &[f64]) -> Result<f64> {
+        let (base, x) = (args[0], args[1]);
+        if base <= 0.0 || base == 1.0 {
+            return Err(CalcError::Domain("invalid logarithm base"));
+        }
+        Ok(logarithm(x, f64::ln)? / base.ln())
+    }
+
+    pub const VARIADIC: usize = usize::MAX;
+
+    /// Folds variadic arguments with `f`, starting from its `identity`.
+    fn reduce(args: &[f64], identity: f64, f: fn(f64, f64) -> f64) -> f64 {
+        args.iter().copied().fold(identity, f)
+    }
+
+    fn mean(args: &[f64]) -> f64 {
+        args.iter().sum::<f64>() / args.len() as f64
+    }
+
+    /// The sample variance, dividing by `n - 1`.
+    fn var(args: &[f64]) -> Result<f64> {
+        if args.len() < 2 {
+            return Err(CalcError::InvalidArguments("variance needs at least two values"));
+        }
+        let mean = mean(args);
+        Ok(args.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (args.len() - 1) as f64)
+    }
+
+    /// The value below which `p` percent of the data fall, interpolating
+    /// linearly between the closest ranks, so the 50th is the median.
+    fn percentile(data: &[f64], p: f64) -> Result<f64> {
+        if ! (0.0..=100.0).contains(&p) {
+            return Err(CalcError::InvalidArguments("percentile out of range from 0 to 100"));
+        }
+        if data.iter().any(|x| x.is_nan()) {
+            return Err(CalcError::InvalidArguments("data contain NaN"));
+        }
+        let mut data = data.to_vec();
+        data.sort_by(f64::total_cmp);
+        let rank = p / 100.0 * (data.len() - 1) as f64;
+        let (below, above) = (data[rank.floor() as usize], data[rank.ceil() as usize]);
+        Ok(below + (above - below) * rank.fract())
+    }
+
+    fn percentile_of(args: &[Value]) -> Result<Value> {
+        let data = vector(&args[0])?;
+        if data.is_empty() {
+            return Err(CalcError::InvalidArguments("percentile of an empty list"));
+        }
+        percentile(&data, args[1].as_number()?).map(Value::Number)
+    }
+
+    fn vector(arg: &Value) -> Result<Vec<f64>> {
+        match arg {
+            Value::List(items) => items.iter().map(Value::as_number).collect(),
+            _ => Err(CalcError::TypeMismatch("expected a list")),
+        }
+    }
+
+    fn dot(args: &[Value]) -> Result<f64> {
+        let (a, b) = (vector(&args[0])?, vector(&args[1])?);
+        if a.len() != b.len() {
+            return Err(CalcError::TypeMismatch("vectors differ in length"));
+        }
+        Ok(a.iter().zip(&b).map(|(x, y)| x * y).sum())
+    }
+
+    fn cross(args: &[Value]) -> Result<Value> {
+        let (a, b) = (vector(&args[0])?, vector(&args[1])?);
+        if a.len() != 3 || b.len() != 3 {
+            return Err(CalcError::TypeMismatch("cross product of vectors that are not of length 3"));
+        }
+        let product = [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]];
+        Ok(Value::List(product.into_iter().map(Value::Number).collect()))
+    }
+
+    fn clamp(args: &[f64]) -> Result<f64> {
+        let (x, lo, hi) = (args[0], args[1], args[2]);
+        if lo > hi {
+            Err(CalcError::InvalidArguments("lower bound of clamp exceeds its upper bound"))
+        } else {
+            Ok(x.clamp(lo, hi))
+        }
+    }
+
+    /// Rounds half away from zero, to `digits` decimal places if given.
+    fn round(args: &[f64]) -> Result<f64> {
+        let x = args[0];
+        let digits = match args.get(1) {
+            None => return Ok(x.round()),
+            Some(digits) if digits.fract() != 0.0 => return Err(CalcError::InvalidArguments("number of digits is not an integer")),
+            // powers of ten beyond these overflow or vanish
+            Some(digits) => digits.clamp(-308.0, 308.0) as i32,
+        };
+        // dividing by an exact power of ten is more precise than
+        // multiplying by its inexact reciprocal
+        let scale = 10f64.powi(digits.abs());
+        let scaled = if digits >= 0 { x * scale } else { x / scale };
+        // a number too large to scale has no digits left to round
+        Ok(match (scaled.is_finite(), digits >= 0) {
+            (false, _) => x,
+            (true, true) => scaled.round() / scale,
+            (true, false) => scaled.round() * scale,
+        })
+    }
+
+    fn remainder(args: &[f64], remainder: Remainder) -> Result<f64> {
+        match args[1] {
+            0.0 => Err(CalcError::DivisionByZero),
+            divisor => Ok(remainder.apply(args[0], divisor)),
+        }
+    }
+
+    fn integer_arg(x: f64) -> Result<i64> {
+        if x.fract() != 0.0 {
+            Err(CalcError::TypeMismatch("argument is not an integer"))
+        } else if x >= i64::MIN as f64 && x < i64::MAX as f64 {
+            Ok(x as i64)
+        } else {
+            Err(CalcError::Overflow("integer out of range"))
+        }
+    }
+
+    fn big_integer(arg: &Value) -> Result<Option<BigInt>> {
+        Ok(match arg {
+            Value::Integer(integer) | Value::Radix(integer, _) => Some(integer.clone()),
+            Value::Decimal(decimal) => decimal.to_integer(),
+            arg => BigInt::from_f64(arg.as_number()?),
+        })
+    }
+
+    fn radix(arg: &Value, radix: u32) -> Result<Value> {
+        big_integer(arg)?.map(|integer| Value::Radix(integer, radix)).ok_or(CalcError::TypeMismatch("only integers can be shown in another base"))
+    }
+
+    fn powmod(args: &[Value]) -> Result<Value> {
+        let integer = |arg| big_integer(arg)?.ok_or(CalcError::TypeMismatch("argument is not an integer"));
+        let (base, exponent, modulus) = (integer(&args[0])?, integer(&args[1])?, integer(&args[2])?);
+        if modulus.is_zero() {
+            return Err(CalcError::DivisionByZero);
+        }
+        base.pow_mod(&exponent, &modulus).map(Value::Integer).ok_or(CalcError::Domain("exponent is negative"))
+    }
+
+    /// A non-negative integer argument of up to 64 bits.
+    fn natural(arg: &Value) -> Result<u64> {
+        match arg {
+            Value::Integer(integer) | Value::Radix(integer, _) if integer.is_negative() => Err(CalcError::Domain("argument is negative")),
+            Value::Integer(integer) | Value::Radix(integer, _) => integer.to_u64().ok_or(CalcError::Overflow("integer out of range")),
+            arg => count_arg(arg.as_number()?),
+        }
+    }
+
+    fn integer(n: u64) -> Value {
+        Value::Integer(BigInt::from_u64(n))
+    }
+
+    fn factor(arg: &Value) -> Result<Value> {
+        match natural(arg)? {
+            0 => Err(CalcError::Domain("factorization of zero")),
+            n => Ok(Value::List(primes::factor(n).into_iter().map(integer).collect())),
+        }
+    }
+
+    /// The numbers from `start` to `end` inclusive, `step` apart; exact
+    /// when all three are integers.
+    fn range(args: &[Value], settings: &Settings) -> Result<Value> {
+        const MAX_ELEMENTS: f64 = 1e6;
+
+        let (start, end) = (args[0].as_number()?, args[1].as_number()?);
+        let step = match args.get(2) {
+            Some(step) => step.as_number()?,
+            None if start > end => -1.0,
+            None => 1.0,
+        };
+        if step == 0.0 || ! step.is_finite() {
+            return Err(CalcError::InvalidArguments("step of a range must be nonzero"));
+        }
+        // a little slack lets `0..1 step 0.1` reach 1 despite rounding
+        let count = ((end - start) / step + 1e-9).floor() + 1.0;
+        if count.is_nan() || count > MAX_ELEMENTS {
+            return Err(CalcError::Overflow("too many elements"));
+        }
+        let exact = [start, end, step].iter().all(|x| x.fract() == 0.0 && x.abs() < 1e15);
+        Ok(Value::List((0..count.max(0.0) as i64).map(|i| match exact {
+            true => {
+                let n = start as i64 + i * step as i64;
+                if settings.decimal { Value::Decimal(Decimal::from(n)) } else { Value::Integer(BigInt::from(n)) }
+            },
+            false => Value::Number(math::significant(start + i as f64 * step)),
+        }).collect()))
+    }
+
+    /// `compose(f, g)` is the function `x -> f(g(x))`, taking the parameters
+    /// of `g` if it is user-defined. The functions are captured under names
+    /// no input can spell.
+    fn compose(args: &[Value]) -> Result<Value> {
+        if ! args.iter().all(Value::is_function) {
+            return Err(CalcError::InvalidArguments("expected functions to compose"));
+        }
+        let params = match args.last() {
+            Some(Value::Function(closure)) => closure.function.params.clone(),
+            _ => vec!["x".to_string()],
+        };
+        let name = |i: usize| format!("#{}", i);
+        let innermost = Expr::Call(name(args.len() - 1), params.iter().cloned().map(Expr::Variable).collect());
+        let body = (0..args.len() - 1).rev().fold(innermost, |body, i| Expr::Call(name(i), vec![body]));
+        let captured = args.iter().enumerate().map(|(i, function)| (name(i), function.clone())).collect();
+        Ok(Value::Function(Rc::new(Closure { function: Rc::new(Function { params, body }), captured })))
+    }
+
+    fn len(arg: &Value) -> Result<Value> {
+        match arg {
+            Value::List(items) => Ok(Value::Integer(BigInt::from(items.len() as i64))),
+            _ => Err(CalcError::TypeMismatch("expected a list")),
+        }
+    }
+
+    /// Joins lists, taking other values as single elements.
+    fn concat(args: &[Value]) -> Value {
+        Value::List(args.iter().flat_map(|arg| match arg {
+            Value::List(items) => items.clone(),
+            arg => vec![arg.clone()],
+        }).collect())
+    }
+
+    /// Swaps rows and columns; a list of numbers becomes a column.
+    fn transpose(arg: &Value) -> Result<Value> {
+        if let Some(rows) = arg.as_matrix() {
+            let columns = (0..rows[0].len()).map(|j| Value::List(rows.iter().map(|row| row[j].clone()).collect()));
+            return Ok(Value::List(columns.collect()));
+        }
+        match arg {
+            Value::List(items) if items.iter().any(|item| matches!(item, Value::List(_))) => Err(CalcError::TypeMismatch("matrix rows differ in length")),
+            Value::List(items) => Ok(Value::List(items.iter().map(|item| Value::List(vec![item.clone()])).collect())),
+            _ => Err(CalcError::TypeMismatch("expected a matrix")),
+        }
+    }
+
+    /// The optional payment timing of the finance functions: 0 at the end
+    /// of each period, 1 at the beginning.
+    fn due(arg: Option<&f64>) -> Result<f64> {
+        match arg.copied().unwrap_or(0.0) {
+            due @ (0.0 | 1.0) => Ok(due),
+            _ => Err(CalcError::InvalidArguments("payment timing must be 0 for the end of periods or 1 for the beginning")),
+        }
+    }
+
+    fn compound(args: &[f64]) -> Result<f64> {
+        if args[2] <= 0.0 {
+            return Err(CalcError::InvalidArguments("compounding periods per year must be positive"));
+        }
+        Ok(finance::compound(args[0], args[1], args[2], args[3]))
+    }
+
+    /// The change from `old` to `new` in percent of `old`.
+    fn pctchange(args: &[f64]) -> Result<f64> {
+        let (old, new) = (args[0], args[1]);
+        if old == 0.0 {
+            return Err(CalcError::Domain("percent change from zero"));
+        }
+        Ok((new - old) / old.abs() * 100.0)
+    }
+
+    /// The profit in percent of the `price`.
+    fn margin(args: &[f64]) -> Result<f64> {
+        let (price, cost) = (args[0], args[1]);
+        if price == 0.0 {
+            return Err(CalcError::Domain("margin of a zero price"));
+        }
+        Ok((price - cost) / price * 100.0)
+    }
+
+    fn irr(flows: &[f64]) -> Result<f64> {
+        if ! (flows.iter().any(|flow| *flow < 0.0) && flows.iter().any(|flow| *flow > 0.0)) {
+            return Err(CalcError::InvalidArguments("cash flows need both signs to have a rate of return"));
+        }
+        finance::irr(flows).map(math::significant).ok_or(CalcError::NoSolution("no rate of return found"))
+    }
+
+    /// The bits of an argument of the bit functions: exact integers up to
+    /// 64 bits as they are and negative ones in two's complement, and
+    /// other numbers like operands of the bitwise operators.
+    fn bits(arg: &Value, settings: &Settings) -> Result<u64> {
+        let exact = match arg {
+            Value::Integer(integer) | Value::Radix(integer, _) => Some(integer.clone()),
+            Value::Decimal(decimal) => decimal.to_integer(),
+            _ => None,
+        };
+        match exact {
+            Some(exact) => exact.to_u64().or_else(|| exact.to_i64().map(|exact| exact as u64))
+                .ok_or(CalcError::Overflow("integer out of range")),
+            None => Ok(eval::to_integer(arg.as_number()?, settings.integer_conversion)? as u64),
+        }
+    }
+
+    /// Counts bits of an integer with `count`, or of each of a list of them.
+    fn count_bits(arg: &Value, settings: &Settings, count: fn(u64) -> u32) -> Result<Value> {
+        match arg {
+            Value::List(items) => items.iter().map(|item| count_bits(item, settings, count)).collect::<Result<_>>().map(Value::List),
+            arg => Ok(integer(count(bits(arg, settings)?) as u64)),
+        }
+    }
+
+    /// Rotates the lowest `width` bits, 64 by default, to the left, or to the
+    /// right by a negative amount, giving them as an unsigned integer.
+    fn rotate_left(args: &[Value], amount: i64, settings: &Settings) -> Result<Value> {
+        let width = match args.get(2) {
+            None => 64,
+            Some(width) => u32::try_from(integer_arg(width.as_number()?)?).ok().filter(|width| (1..=64).contains(width))
+                .ok_or(CalcError::InvalidArguments("width out of range from 1 to 64"))?,
+        };
+        let x = bits(&args[0], settings)?;
+        let mask = u64::MAX >> (64 - width);
+        if x > mask {
+            return Err(CalcError::Domain("operand does not fit in the width"));
+        }
+        let shift = amount.rem_euclid(width as i64) as u32;
+        Ok(integer(if shift == 0 { x } else { (x << shift | x >> (width - shift)) & mask }))
+    }
+
+    fn gcd_of(a: u64, b: u64) -> u64 {
+        if b == 0 { a } else { gcd_of(b, a % b) }
+    }
+
+    fn gcd(args: &[f64]) -> Result<f64> {
+        args.iter().try_fold(0, |result, x| Ok(gcd_of(result, integer_arg(*x)?.unsigned_abs())))
+            .map(|result| result as f64)
+    }
+
+    fn lcm(args: &[f64]) -> Result<f64> {
+        args.iter().try_fold(1u64, |result, x| {
+            let x = integer_arg(*x)?.unsigned_abs();
+            if x == 0 || result == 0 {
+                return Ok(0);
+            }
+            (result / gcd_of(result, x)).checked_mul(x).ok_or(CalcError::Overflow("integer out of range"))
+        }).map(|result| result as f64)
+    }
+
+    fn count_arg(x: f64) -> Result<u64> {
+        u64::try_from(integer_arg(x)?).map_err(|_| CalcError::Domain("argument is negative"))
+    }
+
+    /// `n! / (n - k)!` as the product of the `k` largest factors of `n!`,
+    /// failing instead of reaching infinity.
+    fn falling_factorial(n: u64, k: u64) -> Result<f64> {
+        (n - k + 1..=n).try_fold(1.0, |product, factor| {
+            let product = product * factor as f64;
+            if product.is_finite() { Ok(product) } else { Err(CalcError::Overflow("result overflows")) }
+        })
+    }
+
+    fn npr(args: &[f64]) -> Result<f64> {
+        let (n, k) = (count_arg(args[0])?, count_arg(args[1])?);
+        if k > n {
+            return Ok(0.0);
+        }
+        falling_factorial(n, k)
+    }
+
+    fn ncr(args: &[f64]) -> Result<f64> {
+        let (n, k) = (count_arg(args[0])?, count_arg(args[1])?);
+        if k > n {
+            return Ok(0.0);
+        }
+        let k = k.min(n - k);
+        // every partial product is itself a binomial coefficient, so the
+        // division is exact as long as f64 keeps up
+        (0..k).try_fold(1.0, |result, i| {
+            let result = result * (n - i) as f64 / (i + 1) as f64;
+            if result.is_finite() { Ok(result) } else { Err(CalcError::Overflow("result overflows")) }
+        })
+    }
+
+    fn randint(args: &[f64], random: &Generator) -> Result<f64> {
+        let (lo, hi) = (integer_arg(args[0])?, integer_arg(args[1])?);
+        if lo > hi {
+            return Err(CalcError::InvalidArguments("lower bound of randint exceeds its upper bound"));
+        }
+        let span = hi.abs_diff(lo).wrapping_add(1);
+        let offset = if span == 0 { random.next_u64() } else { random.next_u64() % span };
+        Ok(lo.wrapping_add(offset as i64) as f64)
+    }
+
+    fn seed(args: &[f64], random: &Generator) -> Result<f64> {
+        random.seed(integer_arg(args[0])? as u64);
+        Ok(args[0])
+    }
+
+    const BUILTINS: &[Builtin] = &[
+        Builtin { name: "sqrt", arity: 1..=1, call: Real(|args, _| sqrt(args)) },
+        Builtin { name: "cbrt", arity: 1..=1, call: Real(|args, _| Ok(args[0].cbrt())) },
+        Builtin { name: "exp", arity: 1..=1, call: Real(|args, _| Ok(args[0].exp())) },
+        Builtin { name: "ln", arity: 1..=1, call: Real(|args, _| logarithm(args[0], f64::ln)) },
+        Builtin { name: "log10", arity: 1..=1, call: Real(|args, _| logarithm(args[0], f64::log10)) },
+        Builtin { name: "log2", arity: 1..=1, call: Real(|args, _| logarithm(args[0], f64::log2)) },
+        Builtin { name: "log", arity: 2..=2, call: Real(|args, _| log(args)) },
+        Builtin { name: "abs", arity: 1..=1, call: Real(|args, _| Ok(args[0].abs())) },
+        Builtin { name: "arg", arity: 1..=1, call: Real(|args, _| Ok(0f64.atan2(args[0]))) },
+        Builtin { name: "conj", arity: 1..=1, call: Real(|args, _| Ok(args[0])) },
+        Builtin { name: "re", arity: 1..=1, call: Real(|args, _| Ok(args[0])) },
+        Builtin { name: "im", arity: 1..=1, call: Real(|_, _| Ok(0.0)) },
+        Builtin { name: "round", arity: 1..=2, call: Real(|args, _| round(args)) },
+        Builtin { name: "floor", arity: 1..=1, call: Real(|args, _| Ok(args[0].floor())) },
+        Builtin { name: "ceil", arity: 1..=1, call: Real(|args, _| Ok(args[0].ceil())) },
+        Builtin { name: "trunc", arity: 1..=1, call: Real(|args, _| Ok(args[0].trunc())) },
+        Builtin { name: "min", arity: 1..=VARIADIC, call: Real(|args, _| Ok(reduce(args, f64::INFINITY, f64::min))) },
+        Builtin { name: "max", arity: 1..=VARIADIC, call: Real(|args, _| Ok(reduce(args, f64::NEG_INFINITY, f64::max))) },
+        Builtin { name: "sum", arity: 0..=VARIADIC, call: Real(|args, _| Ok(reduce(args, 0.0, |a, b| a + b))) },
+        Builtin { name: "prod", arity: 0..=VARIADIC, call: Real(|args, _| Ok(reduce(args, 1.0, |a, b| a * b))) },
+        Builtin { name: "mean", arity: 1..=VARIADIC, call: Real(|args, _| Ok(mean(args))) },
+        Builtin { name: "median", arity: 1..=VARIADIC, call: Real(|args, _| percentile(args, 50.0)) },
+        Builtin { name: "var", arity: 1..=VARIADIC, call: Real(|args, _| var(args)) },
+        Builtin { name: "stdev", arity: 1..=VARIADIC, call: Real(|args, _| var(args).map(f64::sqrt)) },
+        Builtin { name: "percentile", arity: 2..=2, call: Values(|args, _| percentile_of(args)) },
+        Builtin { name: "dot", arity: 2..=2, call: Values(|args, _| dot(args).map(Value::Number)) },
+        Builtin { name: "cross", arity: 2..=2, call: Values(|args, _| cross(args)) },
+        Builtin { name: "norm", arity: 1..=1, call: Values(|args, _| Ok(Value::Number(dot(&[args[0].clone(), args[0].clone()])?.sqrt()))) },
+        Builtin { name: "clamp", arity: 3..=3, call: Real(|args, _| clamp(args)) },
+        Builtin { name: "mod", arity: 2..=2, call: Real(|args, _| remainder(args, Remainder::Euclidean)) },
+        Builtin { name: "rem", arity: 2..=2, call: Real(|args, _| remainder(args, Remainder::Truncated)) },
+        Builtin { name: "gcd", arity: 2..=VARIADIC, call: Real(|args, _| gcd(args)) },
+        Builtin { name: "lcm", arity: 2..=VARIADIC, call: Real(|args, _| lcm(args)) },
+        Builtin { name: "fact", arity: 1..=1, call: Real(|args, _| { let n = count_arg(args[0])?; falling_factorial(n, n) }) },
+        Builtin { name: "powmod", arity: 3..=3, call: Values(|args, _| powmod(args)) },
+        Builtin { name: "isprime", arity: 1..=1, call: Values(|args, _| Ok(Value::Number(if primes::is_prime(natural(&args[0])?) { 1.0 } else { 0.0 }))) },
+        Builtin { name: "nextprime", arity: 1..=1, call: Values(|args, _| primes::next_prime(natural(&args[0])?).map(integer).ok_or(CalcError::Overflow("integer out of range"))) },
+        Builtin { name: "factor", arity: 1..=1, call: Values(|args, _| factor(&args[0])) },
+        Builtin { name: "gamma", arity: 1..=1, call: Real(|args, _| gamma(args[0])) },
+        Builtin { name: "lgamma", arity: 1..=1, call: Real(|args, _| lgamma(args[0])) },
+        Builtin { name: "beta", arity: 2..=2, call: Real(|args, _| beta(args)) },
+        Builtin { name: "erf", arity: 1..=1, call: Real(|args, _| Ok(math::erf(args[0]))) },
+        Builtin { name: "erfc", arity: 1..=1, call: Real(|args, _| Ok(math::erfc(args[0]))) },
+        Builtin { name: "pmt", arity: 3..=5, call: Real(|args, _| Ok(finance::pmt(args[0], args[1], args[2], args.get(3).copied().unwrap_or(0.0), due(args.get(4))?))) },
+        Builtin { name: "fv", arity: 3..=5, call: Real(|args, _| Ok(finance::fv(args[0], args[1], args[2], args.get(3).copied().unwrap_or(0.0), due(args.get(4))?))) },
+        Builtin { name: "pctchange", arity: 2..=2, call: Real(|args, _| pctchange(args)) },
+        Builtin { name: "markup", arity: 2..=2, call: Real(|args, _| Ok(args[0] * (1.0 + args[1] / 100.0))) },
+        Builtin { name: "margin", arity: 2..=2, call: Real(|args, _| margin(args)) },
+        Builtin { name: "compound", arity: 4..=4, call: Real(|args, _| compound(args)) },
+        Builtin { name: "ccompound", arity: 3..=3, call: Real(|args, _| Ok(finance::compound_continuously(args[0], args[1], args[2]))) },
+        Builtin { name: "npv", arity: 2..=VARIADIC, call: Real(|args, _| Ok(finance::npv(args[0], &args[1..]))) },
+        Builtin { name: "irr", arity: 1..=VARIADIC, call: Real(|args, _| irr(args)) },
+        Builtin { name: "npr", arity: 2..=2, call: Real(|args, _| npr(args)) },
+        Builtin { name: "ncr", arity: 2..=2, call: Real(|args, _| ncr(args)) },
+        Builtin { name: "rand", arity: 0..=0, call: Random(|_, random| Ok(random.uniform())) },
+        Builtin { name: "randn", arity: 0..=0, call: Random(|_, random| Ok(random.normal())) },
+        Builtin { name: "randint", arity: 2..=2, call: Random(randint) },
+        Builtin { name: "seed", arity: 1..=1, call: Random(seed) },
+        Builtin { name: "sin", arity: 1..=1, call: Real(|args, settings| Ok(settings.angle.to_radians(args[0]).sin())) },
+        Builtin { name: "cos", arity: 1..=1, call: Real(|args, settings| Ok(settings.angle.to_radians(args[0]).cos())) },
+        Builtin { name: "tan", arity: 1..=1, call: Real(|args, settings| Ok(settings.angle.to_radians(args[0]).tan())) },
+        Builtin { name: "asin", arity: 1..=1, call: Real(|args, settings| Ok(settings.angle.express(inverse(args[0], -1.0..=1.0, f64::asin)?))) },
+        Builtin { name: "acos", arity: 1..=1, call: Real(|args, settings| Ok(settings.angle.express(inverse(args[0], -1.0..=1.0, f64::acos)?))) },
+        Builtin { name: "atan", arity: 1..=1, call: Real(|args, settings| Ok(settings.angle.express(args[0].atan()))) },
+        Builtin { name: "sinh", arity: 1..=1, call: Real(|args, _| Ok(args[0].sinh())) },
+        Builtin { name: "cosh", arity: 1..=1, call: Real(|args, _| Ok(args[0].cosh())) },
+        Builtin { name: "tanh", arity: 1..=1, call: Real(|args, _| Ok(args[0].tanh())) },
+        Builtin { name: "asinh", arity: 1..=1, call: Real(|args, _| Ok(args[0].asinh())) },
+        Builtin { name: "acosh", arity: 1..=1, call: Real(|args, _| inverse(args[0], 1.0..=f64::INFINITY, f64::acosh)) },
+        Builtin { name: "atanh", arity: 1..=1, call: Real(|args, _| atanh(args[0])) },
+        Builtin { name: "atan2", arity: 2..=2, call: Real(|args, settings| Ok(settings.angle.express(args[0].atan2(args[1])))) },
+        Builtin { name: "hypot", arity: 2..=2, call: Real(|args, _| Ok(args[0].hypot(args[1]))) },
+        Builtin { name: "popcount", arity: 1..=1, call: Values(|args, settings| count_bits(&args[0], settings, u64::count_ones)) },
+        Builtin { name: "clz", arity: 1..=1, call: Values(|args, settings| count_bits(&args[0], settings, u64::leading_zeros)) },
+        Builtin { name: "ctz", arity: 1..=1, call: Values(|args, settings| count_bits(&args[0], settings, u64::trailing_zeros)) },
+        Builtin { name: "rotl", arity: 2..=3, call: Values(|args, settings| rotate_left(args, integer_arg(args[1].as_number()?)?, settings)) },
+        Builtin { name: "rotr", arity: 2..=3, call: Values(|args, settings| rotate_left(args, integer_arg(args[1].as_number()?)?.wrapping_neg(), settings)) },
+        Builtin { name: "bin", arity: 1..=1, call: Values(|args, _| radix(&args[0], 2)) },
+        Builtin { name: "oct", arity: 1..=1, call: Values(|args, _| radix(&args[0], 8)) },
+        Builtin { name: "hex", arity: 1..=1, call: Values(|args, _| radix(&args[0], 16)) },
+        Builtin { name: "range", arity: 2..=3, call: Values(range) },
+        Builtin { name: "compose", arity: 1..=VARIADIC, call: Values(|args, _| compose(args)) },
+        Builtin { name: "len", arity: 1..=1, call: Values(|args, _| len(&args[0])) },
+        Builtin { name: "transpose", arity: 1..=1, call: Values(|args, _| transpose(&args[0])) },
+        Builtin { name: "concat", arity: 0..=VARIADIC, call: Values(|args, _| Ok(concat(args))) },
+        Builtin { name: "deg", arity: 1..=1, call: Real(|args, _| Ok(args[0].to_degrees())) },
+        Builtin { name: "rad", arity: 1..=1, call: Real(|args, _| Ok(args[0].to_radians())) },
+    ];
+
+    pub fn lookup(name: &str) -> Option<&'static Builtin> {
+        BUILTINS.iter().find(|builtin| builtin.name == name)
+    }
+
+    type ComplexFn = fn(Complex) -> Result<Complex>;
+
+    /// Complex versions of single argument builtins.
+    const COMPLEX_BUILTINS: &[(&str, ComplexFn)] = &[
+        ("sqrt", |z| Ok(z.sqrt())),
+        ("ln", |z| if z.re == 0.0 && z.im == 0.0 { Err(CalcError::Domain("logarithm of zero")) } else { Ok(z.ln()) }),
+        ("abs", |z| Ok(Complex::new(z.abs(), 0.0))),
+        ("arg", |z| Ok(Complex::new(z.arg(), 0.0))),
+        ("conj", |z| Ok(z.conj())),
+        ("re", |z| Ok(Complex::new(z.re, 0.0))),
+        ("im", |z| Ok(Complex::new(z.im, 0.0))),
+    ];
+
+    pub fn complex(name: &str) -> Option<ComplexFn> {
+        COMPLEX_BUILTINS.iter().find(|(complex, _)| *complex == name).map(|(_, call)| *call)
+    }
+
+    /// the most factors multiplied out exactly, as for `n!`
+    const MAX_FACTORS: u64 = 5000;
+
+    pub type ExactFn = fn(&[BigInt]) -> Option<BigInt>;
+
+    /// Exact versions of builtins of integers, for integer arguments, which
+    /// the real ones would round beyond 2^53. They give `None` to leave it
+    /// to the real one, like for negative arguments or too many factors.
+    const EXACT_BUILTINS: &[(&str, ExactFn)] = &[
+        ("fact", |args| exact_npr(&args[0], &args[0])),
+        ("npr", |args| exact_npr(&args[0], &args[1])),
+        ("ncr", |args| exact_ncr(&args[0], &args[1])),
+        ("abs", |args| Some(args[0].abs())),
+        ("floor", |args| Some(args[0].clone())),
+        ("ceil", |args| Some(args[0].clone())),
+        ("trunc", |args| Some(args[0].clone())),
+        ("round", |args| exact_round(&args[0], args.get(1))),
+        ("min", |args| args.iter().min().cloned()),
+        ("max", |args| args.iter().max().cloned()),
+        ("sum", |args| Some(args.iter().fold(BigInt::from(0), |sum, x| &sum + x))),
+        ("prod", |args| exact_prod(args)),
+        ("mod", |args| exact_remainder(&args[0], &args[1], Remainder::Euclidean)),
+        ("rem", |args| exact_remainder(&args[0], &args[1], Remainder::Truncated)),
+        ("gcd", |args| Some(args.iter().fold(BigInt::from(0), |result, x| result.gcd(x)))),
+        ("lcm", |args| exact_lcm(args)),
+    ];
+
+    pub fn exact(name: &str) -> Option<ExactFn> {
+        EXACT_BUILTINS.iter().find(|(exact, _)| *exact == name).map(|(_, call)| *call)
+    }
+
+    /// Rounds half away from zero to a place before the point, like
+    /// `round(25, -1)`; integers have no digits after it to round.
+    fn exact_round(x: &BigInt, digits: Option<&BigInt>) -> Option<BigInt> {
+        let places = match digits {
+            Some(digits) if digits.is_negative() => digits.abs().to_u64()?,
+            _ => return Some(x.clone()),
+        };
+        // 10^places is beyond twice `x`, which rounds to 0
+        if places.saturating_mul(3) > x.bits() {
+            return Some(BigInt::from(0));
+        }
+        let scale = BigInt::from(10).pow(places);
+        let (quotient, rest) = x.divrem(&scale)?;
+        let quotient = match &rest.abs() * &BigInt::from(2) >= scale {
+            true if x.is_negative() => &quotient - &BigInt::from(1),
+            true => &quotient + &BigInt::from(1),
+            false => quotient,
+        };
+        Some(&quotient * &scale)
+    }
+
+    /// The product, left to floating point once it outgrows exact integers.
+    fn exact_prod(args: &[BigInt]) -> Option<BigInt> {
+        args.iter().try_fold(BigInt::from(1), |product, x| {
+            let product = &product * x;
+            (product.bits() <= MAX_BITS).then_some(product)
+        })
+    }
+
+    /// The remainder of `mod` or `rem`; dividing by zero is left to the
+    /// real version to report.
+    fn exact_remainder(a: &BigInt, b: &BigInt, remainder: Remainder) -> Option<BigInt> {
+        let (_, rest) = a.divrem(b)?;
+        Some(match remainder {
+            Remainder::Euclidean if rest.is_negative() => &rest + &b.abs(),
+            _ => rest,
+        })
+    }
+
+    fn exact_lcm(args: &[BigInt]) -> Option<BigInt> {
+        args.iter().try_fold(BigInt::from(1), |result, x| {
+            if x.is_zero() || result.is_zero() {
+                return Some(BigInt::from(0));
+            }
+            let (quotient, _) = result.divrem(&result.gcd(x))?;
+            let result = (&quotient * x).abs();
+            (result.bits() <= MAX_BITS).then_some(result)
+        })
+    }
+
+    fn exact_npr(n: &BigInt, k: &BigInt) -> Option<BigInt> {
+        let (n, k) = (n.to_u64()?, k.to_u64()?);
+        if k > n {
+            return Some(BigInt::from(0));
+        }
+        (k <= MAX_FACTORS).then(|| (n - k + 1..=n).fold(BigInt::from(1), |product, factor| &product * &BigInt::from_u64(factor)))
+    }
+
+    fn exact_ncr(n: &BigInt, k: &BigInt) -> Option<BigInt> {
+        let (n, k) = (n.to_u64()?, k.to_u64()?);
+        if k > n {
+            return Some(BigInt::from(0));
+        }
+        let k = k.min(n - k);
+        if k > MAX_FACTORS {
+            return None;
+        }
+        // every partial product is itself a binomial coefficient, so the
+        // divisions leave no remainder
+        (0..k).try_fold(BigInt::from(1), |result, i| {
+            (&result * &BigInt::from_u64(n - i)).divrem(&BigInt::from_u64(i + 1)).map(|(quotient, _)| quotient)
+        })
+    }
+}
+
+pub mod operators {
+    use std::fmt;
+    use std::rc::Rc;
+
+    use crate::CalcError;
+    use crate::value::Value;
+
+    type Result<T> = std::result::Result<T, CalcError>;
+
+    /// How a chain of operators of the same precedence groups.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Associativity {
+        Left,
+        Right,
+        /// comparisons chain like in mathematics, `1 < x < 10` is
+        /// `1 < x && x < 10`, evaluating `x` twice
+        Chain,
+        /// `a..b..c` is an error
+        None,
+    }
+
+    /// The builtin binary operators with their precedence, from the loosest
+    /// to the tightest binding. The gaps leave room for registered
+    /// operators. The operands of `^` are unary expressions, so `-2^2` is
+    /// `(-2)^2`.
+    pub const BUILTIN: &[(usize, &[&str], Associativity)] = &[
+        (10, &["|>"], Associativity::Left),
+        (20, &["||"], Associativity::Left),
+        (30, &["&&"], Associativity::Left),
+        (40, &["<", "<=", ">", ">=", "==", "!="], Associativity::Chain),
+        (50, &[".."], Associativity::None),
+        (60, &["|"], Associativity::Left),
+        (70, &["xor"], Associativity::Left),
+        (80, &["&"], Associativity::Left),
+        (90, &["<<", ">>"], Associativity::Left),
+        (100, &["+", "-"], Associativity::Left),
+        (110, &["*", "/", "//", "%", "of"], Associativity::Left),
+        (120, &["^"], Associativity::Right),
+    ];
+
+    /// The precedence and associativity of a builtin binary operator.
+    pub fn builtin(symbol: &str) -> Option<(usize, Associativity)> {
+        BUILTIN.iter().find(|(_, symbols, _)| symbols.contains(&symbol))
+            .map(|(precedence, _, associativity)| (*precedence, *associativity))
+    }
+
+    pub struct Binary {
+        pub symbol: &'static str,
+        pub precedence: usize,
+        pub associativity: Associativity,
+        pub eval: Rc<dyn Fn(Value, Value) -> Result<Value>>,
+    }
+
+    /// A prefix operator, whose operand takes in the binary operators
+    /// binding at least as tight as it does.
+    pub struct Unary {
+        pub symbol: &'static str,
+        pub precedence: usize,
+        pub eval: Rc<dyn Fn(Value) -> Result<Value>>,
+    }
+
+    /// Operators registered by an embedder in addition to the builtin ones,
+    /// like a `div` at the precedence of `*`, added with
+    /// `settings.operators.add_binary("div", 110, Associativity::Left, f)`
+    /// before evaluating with `eval_str_with`.
+    #[derive(Clone, Default)]
+    pub struct Operators {
+        binary: Vec<Rc<Binary>>,
+        unary: Vec<Rc<Unary>>,
+    }
+
+    impl fmt::Debug for Operators {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.debug_struct("Operators")
+                .field("binary", &self.binary.iter().map(|operator| operator.symbol).collect::<Vec<_>>())
+                .field("unary", &self.unary.iter().map(|operator| operator.symbol).collect::<Vec<_>>())
+                .finish()
+        }
+    }
+
+    /// Whether the lexer can scan `symbol` as a single token: a word like
+    /// `mod`, or punctuation that does not begin a number, a comment or a
+    /// builtin spelling like `**`.
+    fn is_scannable(symbol: &str) -> bool {
+        let word = symbol.starts_with(char::is_alphabetic) && symbol.chars().all(|c| c.is_alphanumeric() || c == '_');
+        let punctuation = ! symbol.is_empty()
+            && symbol.chars().all(|c| c.is_ascii_punctuation() && ! "#%.$_()[],;".contains(c))
+            && ! symbol.starts_with("**");
+        word || punctuation
+    }
+
+    impl Operators {
+        /// Registers a binary operator, failing for a symbol that is taken
+        /// or cannot be scanned.
+        pub fn add_binary(
+            &mut self,
+            symbol: &'static str,
+            precedence: usize,
+            associativity: Associativity,
+            eval: impl Fn(Value, Value) -> Result<Value> + 'static,
+        ) -> Result<()> {
+            self.check(symbol)?;
+            if self.binary(symbol).is_some() {
+                return Err(CalcError::InvalidDefinition("the operator is already defined"));
+            }
+            self.binary.push(Rc::new(Binary { symbol, precedence, associativity, eval: Rc::new(eval) }));
+            Ok(())
+        }
+
+        /// Registers a prefix operator, failing for a symbol that is taken
+        /// or cannot be scanned.
+        pub fn add_unary(
+            &mut self,
+            symbol: &'static str,
+            precedence: usize,
+            eval: impl Fn(Value) -> Result<Value> + 'static,
+        ) -> Result<()> {
+            self.check(symbol)?;
+            if self.unary(symbol).is_some() {
+                return Err(CalcError::InvalidDefinition("the operator is already defined"));
+            }
+            self.unary.push(Rc::new(Unary { symbol, precedence, eval: Rc::new(eval) }));
+            Ok(())
+        }
+
+        fn check(&self, symbol: &str) -> Result<()> {
+            if crate::is_reserved(symbol) {
+                return Err(CalcError::InvalidDefinition("the operator is already defined"));
+            }
+            // such an operator would hide the function or constant
+            if crate::functions::lookup(symbol).is_some() || crate::math::constant(symbol).is_some() {
+                return Err(CalcError::InvalidDefinition("the symbol names a builtin function or constant"));
+            }
+            if ! is_scannable(symbol) {
+                return Err(CalcError::InvalidDefinition("invalid operator symbol"));
+            }
+            Ok(())
+        }
+
+        pub fn binary(&self, symbol: &str) -> Option<&Binary> {
+            self.binary.iter().find(|operator| operator.symbol == symbol).map(|operator| &**operator)
+        }
+
+        pub fn unary(&self, symbol: &str) -> Option<&Unary> {
+            self.unary.iter().find(|operator| operator.symbol == symbol).map(|operator| &**operator)
+        }
+
+        /// The symbols to scan as operators, of both kinds.
+        pub fn symbols(&self) -> impl Iterator<Item = &'static str> + '_ {
+            self.binary.iter().map(|operator| operator.symbol).chain(self.unary.iter().map(|operator| operator.symbol))
+        }
+    }
+}
+
+pub mod plugins {
+    use std::fmt;
+    use std::ops::RangeInclusive;
+    use std::rc::Rc;
+
+    use crate::{functions, CalcError};
+    use crate::value::Value;
+
+    type Result<T> = std::result::Result<T, CalcError>;
+
+    /// A function supplied by the host application, called like a builtin.
+    pub trait Function {
+        fn name(&self) -> &str;
+        /// accepted numbers of arguments, ending in `functions::VARIADIC`
+        /// for any number
+        fn arity(&self) -> RangeInclusive<usize>;
+        fn call(&self, args: &[Value]) -> Result<Value>;
+    }
+
+    impl fmt::Debug for dyn Function {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "Function({})", self.name())
+        }
+    }
+
+    /// The functions registered in the settings, which neither builtins
+    /// nor other plugins may share a name with.
+    #[derive(Debug, Clone, Default)]
+    pub struct Plugins {
+        functions: Vec<Rc<dyn Function>>,
+    }
+
+    impl Plugins {
+        pub fn add(&mut self, function: impl Function + 'static) -> Result<()> {
+            let name = function.name();
+            if ! name.starts_with(|c: char| c.is_alphabetic() || c == '_') || ! name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+                return Err(CalcError::InvalidDefinition("invalid function name"));
+            }
+            if functions::lookup(name).is_some() || self.get(name).is_some() {
+                return Err(CalcError::InvalidDefinition("the function is already defined"));
+            }
+            self.functions.push(Rc::new(function));
+            Ok(())
+        }
+
+        pub fn get(&self, name: &str) -> Option<Rc<dyn Function>> {
+            self.functions.iter().find(|function| function.name() == name).cloned()
+        }
+    }
+}
+
+pub mod parser {
+    use std::rc::Rc;
+
+    use crate::{Token, Scan, CalcError, Diagnostic, Span, math, functions, units};
+    use crate::units::Target;
+    use crate::ast::{Expr, Function, Statement};
+    use crate::bigint::BigInt;
+    use crate::eval::Settings;
+    use crate::numeric::Numeric;
+    use crate::operators::{self, Associativity};
+    use crate::time::Duration;
+
+    type Result<T> = std::result::Result<T, Diagnostic>;
+
+    pub struct Parser<'a, T> {
+        lexer: &'a mut T,
+        settings: &'a Settings,
+        current: Token,
+        look_ahead: Token,
+        previous_span: Span,
+        current_span: Span,
+        look_ahead_span: Span,
+        /// inside `|...|`, where a `|` closes the bars instead of being a
+        /// bitwise or
+        in_bars: bool,
+        /// the syntax errors found so far, parsing goes on after each of
+        /// them to find the others
+        diagnostics: Vec<Diagnostic>,
+    }
+
+    impl<'a, T: Scan> Parser<'a, T> {
+        pub fn new(lexer: &'a mut T, settings: &'a Settings) -> Self {
+            Parser {
+                lexer,
+                settings,
+                current: Token::Empty,
+                look_ahead: Token::Empty,
+                previous_span: Span::default(),
+                current_span: Span::default(),
+                look_ahead_span: Span::default(),
+                in_bars: false,
+                diagnostics: Vec::new(),
+            }
+        }
+
+        fn shift(&mut self) -> Token {
+            let next = self.lexer.next_token();
+            let look_ahead = std::mem::replace(&mut self.look_ahead, next.node);
+            let look_ahead_span = std::mem::replace(&mut self.look_ahead_span, next.span);
+            self.previous_span = std::mem::replace(&mut self.current_span, look_ahead_span);
+            std::mem::replace(&mut self.current, look_ahead)
+        }
+
+        /// An error at the current token, which cannot come next.
+        fn at_current(&self, error: CalcError) -> Diagnostic {
+            Diagnostic { error, span: self.current_span }
+        }
+
+        /// An error at the token just shifted, which the parser failed at.
+        fn at_previous(&self, error: CalcError) -> Diagnostic {
+            Diagnostic { error, span: self.previous_span }
+        }
+
+        /// Records a syntax error, unless it is the one recorded last,
+        /// passed on from where recovering from it failed.
+        fn report(&mut self, diagnostic: Diagnostic) {
+            if self.diagnostics.last() != Some(&diagnostic) {
+                self.diagnostics.push(diagnostic);
+            }
+        }
+
+        /// Skips the rest of a broken argument, up to the `,` or bracket
+        /// after it, or to the end of the statement.
+        fn skip_argument(&mut self) {
+            let mut depth = 0;
+            loop {
+                match self.current {
+                    Token::End | Token::Operator(";") => return,
+                    Token::Operator("," | ")" | "]") if depth == 0 => return,
+                    Token::Operator("(" | "[") => depth += 1,
+                    Token::Operator(")" | "]") => depth -= 1,
+                    _ => (),
+                }
+                self.shift();
+            }
+        }
+
+        /// Skips the rest of a broken statement.
+        fn skip_statement(&mut self) {
+            while ! matches!(self.current, Token::End | Token::Operator(";")) {
+                self.shift();
+            }
+            self.in_bars = false;
+        }
+
+        fn parse_primary_expr(&mut self) -> Result<Expr> {
+            // what ends an argument or a statement is left there, for the
+            // parser to go on after the error
+            if let Token::End | Token::Operator(")" | "]" | "," | ";") = self.current {
+                return Err(self.at_current(CalcError::UnexpectedToken("invalid operator")));
+            }
+            match self.shift() {
+                Token::Operator("(") => {
+                    let mut items = self.parse_args()?;
+
+                    if let Token::Operator("->") = self.current {
+                        self.shift();
+                        let params = self.params(items)?;
+                        return self.parse_lambda(params);
+                    }
+                    match items.len() {
+                        1 => Ok(items.remove(0)),
+                        0 => Err(self.at_previous(CalcError::UnexpectedToken("empty brackets"))),
+                        _ => Err(self.at_previous(CalcError::UnexpectedToken("unexpected ','"))),
+                    }
+                },
+
+                Token::Operator("[") => {
+                    let mut items = Vec::new();
+                    if let Token::Operator("]") = self.current {
+                        self.shift();
+                        return Ok(Expr::List(items));
+                    }
+                    let outer = std::mem::replace(&mut self.in_bars, false);
+                    let result = self.parse_arg_list(&mut items, "]");
+                    self.in_bars = outer;
+                    result.map(|_| Expr::List(items))
+                },
+
+                Token::Operator("|") => {
+                    let outer = std::mem::replace(&mut self.in_bars, true);
+                    let oprand = self.parse_expr();
+                    self.in_bars = outer;
+                    let oprand = oprand?;
+
+                    match self.shift() {
+                        Token::Operator("|") => Ok(Expr::Call("abs".to_string(), vec![oprand])),
+                        Token::Invalid(error) => Err(self.at_previous(error)),
+                        _ => Err(self.at_previous(CalcError::UnmatchedBar)),
+                    }
+                },
+
+                Token::Literal(literal) => {
+                    let number = self.literal(&literal);
+                    self.parse_quantity(number)
+                },
+
+                Token::Date(date) => Ok(Expr::Date(date)),
+
+                Token::Time(seconds) => Ok(Expr::Duration(Duration(seconds))),
+
+                Token::Operator("let") => self.parse_let(),
+
+                Token::Ident(name) if matches!(self.current, Token::Operator("(")) => {
+                    self.shift();
+                    let mut args = self.parse_args()?;
+                    // `if(condition, then, otherwise)` is a conditional, which
+                    // only evaluates the branch it takes
+                    if name == "if" {
+                        if args.len() != 3 {
+                            return Err(self.at_previous(CalcError::InvalidArguments("if takes a condition and two branches")));
+                        }
+                        let (otherwise, then, condition) = (args.pop().unwrap(), args.pop().unwrap(), args.pop().unwrap());
+                        return Ok(Expr::Conditional(Box::new(condition), Box::new(then), Box::new(otherwise)));
+                    }
+                    Ok(Expr::Call(name, args))
+                },
+
+                Token::Ident(name) if matches!(self.current, Token::Operator("->")) => {
+                    self.shift();
+                    self.parse_lambda(vec![name])
+                },
+
+                Token::Ident(name) => match math::constant(&name) {
+                    Some(value) => Ok(Expr::Number(value)),
+                    None => Ok(Expr::Variable(name)),
+                },
+
+                Token::Invalid(error) => Err(self.at_previous(error)),
+
+                _ => Err(self.at_previous(CalcError::UnexpectedToken("invalid operator"))),
+            }
+        }
+
+        /// Reads a literal exactly where possible: as a decimal in decimal
+        /// mode, otherwise integers as integers and the rest as floats.
+        fn literal(&self, literal: &str) -> Expr {
+            let exact = if self.settings.decimal {
+                Numeric::parse(literal).map(Expr::Decimal)
+            } else {
+                Numeric::parse(literal).map(Expr::Integer)
+            };
+            exact.unwrap_or_else(|| Expr::Number(Numeric::parse(literal).unwrap()))
+        }
+
+        /// A number directly followed by a unit, like `2 s` or `3 m^2`, is a
+        /// quantity binding tighter than any operator, so that `3 m / 2 s`
+        /// divides by `2 s`.
+        fn parse_quantity(&mut self, number: Expr) -> Result<Expr> {
+            let name = match &self.current {
+                Token::Ident(name) if units::lookup(name).is_some() && ! matches!(self.look_ahead, Token::Operator("(")) => {
+                    name.clone()
+                },
+                _ => return Ok(number),
+            };
+            self.shift();
+            let exponent = self.parse_unit_exponent()?;
+            Ok(Expr::Quantity(Box::new(number), name, exponent))
+        }
+
+        /// Parses the optional integer power after a unit, like `^2`, `^-1`
+        /// or `²`.
+        fn parse_unit_exponent(&mut self) -> Result<i32> {
+            match (&self.current, &self.look_ahead) {
+                (Token::Superscript(exponent), _) => {
+                    let exponent = *exponent as i32;
+                    self.shift();
+                    Ok(exponent)
+                },
+                (Token::Operator("^"), Token::Literal(_) | Token::Operator("-")) => {
+                    self.shift();
+                    let negative = matches!(self.current, Token::Operator("-"));
+                    if negative {
+                        self.shift();
+                    }
+                    let exponent = match self.shift() {
+                        Token::Literal(literal) => literal.parse::<i32>().map_err(|_| self.at_previous(CalcError::UnexpectedToken("exponent of a unit is not an integer")))?,
+                        _ => return Err(self.at_previous(CalcError::UnexpectedToken("expected the exponent of a unit"))),
+                    };
+                    Ok(if negative { - exponent } else { exponent })
+                },
+                _ => Ok(1),
+            }
+        }
+
+        /// Parses the unit after `to` or `in`, like `km/h` or `kg*m^2`.
+        fn parse_target(&mut self) -> Result<Target> {
+            let mut units = Vec::new();
+            let mut sign = 1;
+            loop {
+                let name = match self.shift() {
+                    Token::Ident(name) if units::lookup(&name).is_some() => name,
+                    _ => return Err(self.at_previous(CalcError::UnexpectedToken("expected a unit"))),
+                };
+                units.push((name, sign * self.parse_unit_exponent()?));
+
+                sign = match self.current {
+                    Token::Operator("*") => 1,
+                    Token::Operator("/") => -1,
+                    _ => return Ok(Target(units)),
+                };
+                self.shift();
+            }
+        }
+
+        /// Parses an expression optionally converted to another unit, like
+        /// `5 km to miles`; `in` works too, except in a `let` binding. This
+        /// binds looser than any operator and is only found in statements
+        /// and brackets.
+        fn parse_converted_expr(&mut self) -> Result<Expr> {
+            let expr = self.parse_expr()?;
+            self.parse_conversion(expr)
+        }
+
+        fn parse_conversion(&mut self, expr: Expr) -> Result<Expr> {
+            match (&self.current, &self.look_ahead) {
+                // `255 to hex` is `hex(255)`
+                (Token::Operator("to" | "in"), Token::Ident(name)) if matches!(name.as_str(), "hex" | "oct" | "bin") => {
+                    self.shift();
+                    let name = match self.shift() {
+                        Token::Ident(name) => name,
+                        _ => unreachable!(),
+                    };
+                    Ok(Expr::Call(name, vec![expr]))
+                },
+                (Token::Operator("to" | "in"), _) => {
+                    self.shift();
+                    let target = self.parse_target()?;
+                    Ok(Expr::Convert(Box::new(expr), Rc::new(target)))
+                },
+                _ => Ok(expr),
+            }
+        }
+
+        /// Parses the bindings of `let a = 1, b = a + 1 in body` after the
+        /// `let`; later bindings may refer to earlier ones.
+        fn parse_let(&mut self) -> Result<Expr> {
+            let name = match self.shift() {
+                Token::Ident(name) if math::constant(&name).is_some() => return Err(self.at_previous(CalcError::InvalidDefinition("cannot assign to a constant"))),
+                Token::Ident(name) => name,
+                _ => return Err(self.at_previous(CalcError::UnexpectedToken("expected a name after 'let'"))),
+            };
+            if ! matches!(self.shift(), Token::Operator("=")) {
+                return Err(self.at_previous(CalcError::UnexpectedToken("expected '=' in 'let'")));
+            }
+            let value = self.parse_expr()?;
+
+            let body = match self.shift() {
+                Token::Operator(",") => self.parse_let()?,
+                Token::Operator("in") => self.parse_expr()?,
+                _ => return Err(self.at_previous(CalcError::UnexpectedToken("expected 'in' after 'let' bindings"))),
+            };
+            Ok(Expr::Let(name, Box::new(value), Box::new(body)))
+        }
+
+        /// Parses the body of a lambda, which extends as far as possible.
+        fn parse_lambda(&mut self, params: Vec<String>) -> Result<Expr> {
+            let body = self.parse_expr()?;
+            Ok(Expr::Lambda(Rc::new(Function { params, body })))
+        }
+
+        /// Parses a comma separated argument list right after its `(`,
+        /// consuming the closing `)`.
+        fn parse_args(&mut self) -> Result<Vec<Expr>> {
+            let mut args = Vec::new();
+            if let Token::Operator(")") = self.current {
+                self.shift();
+                return Ok(args);
+            }
+
+            // brackets inside `|...|` may hold a bitwise or again
+            let outer = std::mem::replace(&mut self.in_bars, false);
+            let result = self.parse_arg_list(&mut args, ")");
+            self.in_bars = outer;
+            result.map(|_| args)
+        }
+
+        /// Parses comma separated expressions up to the `close` bracket.
+        fn parse_arg_list(&mut self, args: &mut Vec<Expr>, close: &str) -> Result<()> {
+            loop {
+                let arg = match self.parse_converted_expr() {
+                    Ok(arg) => arg,
+                    Err(diagnostic) => {
+                        self.report(diagnostic);
+                        self.skip_argument();
+                        if let Token::End | Token::Operator(";") = self.current {
+                            return Err(diagnostic);
+                        }
+                        // stands in for the broken argument
+                        Expr::Number(f64::NAN)
+                    },
+                };
+                // an equation, like in `solve(2*x + 3 = 11, x)`
+                let arg = match self.current {
+                    Token::Operator("=") => {
+                        self.shift();
+                        Expr::Binary("==", Box::new(arg), Box::new(self.parse_converted_expr()?))
+                    },
+                    _ => arg,
+                };
+                args.push(arg);
+
+                match self.shift() {
+                    Token::Operator(",") => if matches!(self.current, Token::Operator(operator) if operator == close) {
+                        return Err(self.at_current(CalcError::UnexpectedToken("missing argument after ','")));
+                    },
+                    Token::Operator(operator) if operator == close => return Ok(()),
+                    Token::End => return Err(self.at_previous(CalcError::UnmatchedParen)),
+                    Token::Invalid(error) => return Err(self.at_previous(error)),
+                    _ => return Err(self.at_previous(CalcError::UnexpectedToken("missing ',' between arguments"))),
+                }
+            }
+        }
+
+        fn parse_postfix_expr(&mut self) -> Result<Expr> {
+            let mut result = self.parse_primary_expr()?;
+
+            loop {
+                let operator = match self.current {
+                    Token::Operator("!") => "!",
+                    Token::Percent => "%",
+                    Token::Operator("[") => {
+                        self.shift();
+                        let outer = std::mem::replace(&mut self.in_bars, false);
+                        let indexed = self.parse_index(result);
+                        self.in_bars = outer;
+                        result = indexed?;
+                        continue;
+                    },
+                    // a call gives the function `f(x)(2)` calls, where after
+                    // a number or a variable `(` multiplies
+                    Token::Operator("(") if matches!(result, Expr::Call(..) | Expr::Apply(..) | Expr::Lambda(_)) => {
+                        self.shift();
+                        result = Expr::Apply(Box::new(result), self.parse_args()?);
+                        continue;
+                    },
+                    // binds like a postfix operator so that `-x²` is `-(x²)`
+                    Token::Superscript(exponent) => {
+                        self.shift();
+                        let exponent = BigInt::from_f64(exponent).map_or(Expr::Number(exponent), Expr::Integer);
+                        result = Expr::Binary("^", Box::new(result), Box::new(exponent));
+                        continue;
+                    },
+                    _ => return Ok(result),
+                };
+                self.shift();
+
+                result = Expr::Postfix(operator, Box::new(result));
+            }
+        }
+
+        /// The rest of `list[index]` or `list[start:end]` after the `[`.
+        fn parse_index(&mut self, list: Expr) -> Result<Expr> {
+            let list = Box::new(list);
+            let integer = |n: f64| Some(Box::new(Expr::Integer(BigInt::from(n as i64))));
+            // the lexer reads `a[1:30]` as the clock time 1:30
+            if let (Token::Time(seconds), Token::Operator("]")) = (&self.current, &self.look_ahead) {
+                if seconds % 60.0 == 0.0 {
+                    let (start, end) = ((seconds / 3600.0).floor(), (seconds % 3600.0 / 60.0).floor());
+                    self.shift();
+                    self.shift();
+                    return Ok(Expr::Slice(list, integer(start), integer(end)));
+                }
+            }
+            let start = match self.current {
+                Token::Operator(":") => None,
+                _ => Some(Box::new(self.parse_expr()?)),
+            };
+            match (self.shift(), start) {
+                (Token::Operator("]"), Some(index)) => Ok(Expr::Index(list, index)),
+                (Token::Operator(":"), start) => {
+                    let end = match self.current {
+                        Token::Operator("]") => None,
+                        _ => Some(Box::new(self.parse_expr()?)),
+                    };
+                    match self.shift() {
+                        Token::Operator("]") => Ok(Expr::Slice(list, start, end)),
+                        Token::Invalid(error) => Err(self.at_previous(error)),
+                        _ => Err(self.at_previous(CalcError::UnmatchedParen)),
+                    }
+                },
+                (Token::Invalid(error), _) => Err(self.at_previous(error)),
+                _ => Err(self.at_previous(CalcError::UnmatchedParen)),
+            }
+        }
+
+        fn parse_unary_expr(&mut self) -> Result<Expr> {
+            match self.current {
+                Token::Operator(operator @ ("+" | "-" | "!")) => {
+                    self.shift();
+                    let oprand = self.parse_unary_expr()?;
+
+                    Ok(Expr::Unary(operator, Box::new(oprand)))
+                },
+                Token::Operator("√") => {
+                    self.shift();
+                    let oprand = self.parse_unary_expr()?;
+
+                    Ok(Expr::Call("sqrt".to_string(), vec![oprand]))
+                },
+                Token::Operator(operator) if self.settings.operators.unary(operator).is_some() => {
+                    let precedence = self.settings.operators.unary(operator).unwrap().precedence;
+                    self.shift();
+                    let oprand = self.parse_binary_expr(precedence)?;
+
+                    Ok(Expr::Unary(operator, Box::new(oprand)))
+                },
+                _ => self.parse_postfix_expr(),
+            }
+        }
+
+        /// The binary operator at the current token with its precedence and
+        /// associativity, and the number of tokens it takes up: `|>` is lexed
+        /// as `|` and `>`, and an implicit multiplication has no token.
+        fn binary_operator(&self) -> Option<(&'static str, usize, usize, Associativity)> {
+            let (operator, tokens) = match (&self.current, &self.look_ahead) {
+                // inside `|...|` a `|` closes the bars
+                (Token::Operator("|"), _) if self.in_bars => return None,
+                (Token::Operator("|"), Token::Operator(">")) => ("|>", 2),
+                (Token::Operator(operator), _) if self.precedence(operator).is_some() => (*operator, 1),
+                (Token::Operator("(" | "√") | Token::Ident(_), _) if self.settings.implicit_mul => ("*", 0),
+                _ => return None,
+            };
+            let (precedence, associativity) = self.precedence(operator)?;
+            Some((operator, tokens, precedence, associativity))
+        }
+
+        /// The precedence and associativity of a builtin or registered
+        /// binary operator.
+        fn precedence(&self, operator: &str) -> Option<(usize, Associativity)> {
+            operators::builtin(operator).or_else(|| {
+                self.settings.operators.binary(operator).map(|operator| (operator.precedence, operator.associativity))
+            })
+        }
+
+        /// Parses the operands and binary operators binding at least as
+        /// tight as `min_precedence`, see `operators::BUILTIN`.
+        fn parse_binary_expr(&mut self, min_precedence: usize) -> Result<Expr> {
+            let mut result = self.parse_unary_expr()?;
+            // the right operand of the last comparison, compared again by a
+            // chained one
+            let mut chain = None;
+
+            while let Some((operator, tokens, precedence, associativity)) = self.binary_operator() {
+                if precedence < min_precedence {
+                    break;
+                }
+                if operator == "of" {
+                    if ! matches!(result, Expr::Postfix("%", _)) {
+                        return Err(self.at_current(CalcError::UnexpectedToken("'of' must follow a percentage")));
+                    }
+                    result = self.parse_percentage_of(result)?;
+                    continue;
+                }
+                for _ in 0..tokens {
+                    self.shift();
+                }
+                let right_precedence = match associativity {
+                    Associativity::Right => precedence,
+                    _ => precedence + 1,
+                };
+                let right = self.parse_binary_expr(right_precedence)?;
+
+                result = match associativity {
+                    // `a..b` and `a..b step s` are lists, like `range(a, b, s)`
+                    Associativity::None => {
+                        let mut args = vec![result, right];
+                        if let Token::Operator("step") = self.current {
+                            self.shift();
+                            args.push(self.parse_binary_expr(right_precedence)?);
+                        }
+                        if matches!(self.binary_operator(), Some((_, _, next, _)) if next == precedence) {
+                            return Err(self.at_current(CalcError::UnexpectedToken("invalid expression")));
+                        }
+                        Expr::Call("range".to_string(), args)
+                    },
+                    Associativity::Chain => match chain.replace(right.clone()) {
+                        Some(left) => {
+                            let comparison = Expr::Binary(operator, Box::new(left), Box::new(right));
+                            Expr::Binary("&&", Box::new(result), Box::new(comparison))
+                        },
+                        None => Expr::Binary(operator, Box::new(result), Box::new(right)),
+                    },
+                    _ => Expr::Binary(operator, Box::new(result), Box::new(right)),
+                };
+                if associativity != Associativity::Chain {
+                    chain = None;
+                }
+            }
+            Ok(result)
+        }
+
+        /// A percentage of a value, like `20% of 150`, which may itself be a
+        /// percentage of a value: `50% of 20% of 1000` is `100`.
+        fn parse_percentage_of(&mut self, percentage: Expr) -> Result<Expr> {
+            self.shift();
+            let mut value = self.parse_binary_expr(operators::builtin("^").unwrap().0)?;
+            if let (Expr::Postfix("%", _), Token::Operator("of")) = (&value, &self.current) {
+                value = self.parse_percentage_of(value)?;
+            }
+            Ok(Expr::Binary("*", Box::new(percentage), Box::new(value)))
+        }
+
+        fn parse_cond_expr(&mut self) -> Result<Expr> {
+            let condition = self.parse_binary_expr(0)?;
+
+            match self.current {
+                Token::Operator("?") => {
+                    self.shift();
+                    let then = self.parse_cond_expr()?;
+
+                    if let Token::Operator(":") = self.current {
+                        self.shift();
+                    } else {
+                        return Err(self.at_current(CalcError::UnexpectedToken("missing ':' in conditional")));
+                    }
+                    let otherwise = self.parse_cond_expr()?;
+
+                    Ok(Expr::Conditional(Box::new(condition), Box::new(then), Box::new(otherwise)))
+                },
+
+                _ => Ok(condition),
+            }
+        }
+
+        fn parse_expr(&mut self) -> Result<Expr> {
+            self.parse_cond_expr()
+        }
+
+        fn parse_statement(&mut self) -> Result<Statement> {
+            match (&self.current, &self.look_ahead) {
+                (Token::Ident(name), Token::Operator("=")) => {
+                    if math::constant(name).is_some() {
+                        return Err(self.at_current(CalcError::InvalidDefinition("cannot assign to a constant")));
+                    }
+                    let name = name.clone();
+                    self.shift();
+                    self.shift();
+                    Ok(Statement::Assign(name, self.parse_converted_expr()?))
+                },
+
+                // `x += 3` is `x = x + 3`
+                (Token::Ident(name), Token::Operator(operator @ ("+=" | "-=" | "*=" | "/=" | "//=" | "%=" | "^="))) => {
+                    if math::constant(name).is_some() {
+                        return Err(self.at_current(CalcError::InvalidDefinition("cannot assign to a constant")));
+                    }
+                    let name = name.clone();
+                    let operator = &operator[..operator.len() - 1];
+                    self.shift();
+                    self.shift();
+                    let value = Expr::Binary(operator, Box::new(Expr::Variable(name.clone())), Box::new(self.parse_converted_expr()?));
+                    Ok(Statement::Assign(name, value))
+                },
+
+                _ => {
+                    let expr = self.parse_expr()?;
+                    if let Token::Operator("=") = self.current {
+                        self.shift();
+                        let (name, params) = self.function_head(expr)?;
+                        let body = self.parse_converted_expr()?;
+                        Ok(Statement::Function(name, Rc::new(Function { params, body })))
+                    } else {
+                        Ok(Statement::Expr(self.parse_conversion(expr)?))
+                    }
+                },
+            }
+        }
+
+        /// Turns the left hand side of `f(x, y) = ...`, parsed as a call,
+        /// into the function name and its parameters.
+        fn function_head(&self, expr: Expr) -> Result<(String, Vec<String>)> {
+            let (name, args) = match expr {
+                Expr::Call(name, args) => (name, args),
+                _ => return Err(self.at_previous(CalcError::InvalidDefinition("invalid assignment target"))),
+            };
+            if functions::lookup(&name).is_some() {
+                return Err(self.at_previous(CalcError::InvalidDefinition("cannot redefine a builtin function")));
+            }
+            Ok((name, self.params(args)?))
+        }
+
+        /// Checks that expressions parsed as a parameter list are distinct
+        /// plain names.
+        fn params(&self, items: Vec<Expr>) -> Result<Vec<String>> {
+            let mut params: Vec<String> = Vec::new();
+            for item in items {
+                match item {
+                    Expr::Variable(param) if params.contains(&param) => return Err(self.at_previous(CalcError::InvalidDefinition("duplicate parameter"))),
+                    Expr::Variable(param) => params.push(param),
+                    _ => return Err(self.at_previous(CalcError::InvalidDefinition("invalid parameter"))),
+                }
+            }
+            Ok(params)
+        }
+
+        /// Parses a line of statements separated by `;`, empty statements
+        /// are skipped. A broken statement or argument is skipped after
+        /// recording its error, so that all syntax errors are reported.
+        pub fn parse(&mut self) -> std::result::Result<Vec<Statement>, Vec<Diagnostic>> {
+            self.shift();
+            self.shift();
+            let mut statements = Vec::new();
+            loop {
+                match self.current {
+                    Token::End => break,
+                    Token::Operator(";") => {
+                        self.shift();
+                        continue;
+                    },
+                    _ => match self.parse_statement() {
+                        Ok(statement) => statements.push(statement),
+                        Err(diagnostic) => {
+                            self.report(diagnostic);
+                            self.skip_statement();
+                        },
+                    },
+                }
+
+                match self.current {
+                    Token::End => break,
+                    Token::Operator(";") => { self.shift(); },
+                    _ => {
+                        let diagnostic = match self.current {
+                            Token::Invalid(error) => self.at_current(error),
+                            _ => self.at_current(CalcError::UnexpectedToken("invalid expression")),
+                        };
+                        self.report(diagnostic);
+                        self.skip_statement();
+                    },
+                }
+            }
+            match self.diagnostics.is_empty() {
+                true => Ok(statements),
+                false => Err(std::mem::take(&mut self.diagnostics)),
+            }
+        }
+    }
+}
+
+pub mod value {
+    use std::collections::HashMap;
+    use std::fmt;
+    use std::rc::Rc;
+
+    use crate::CalcError;
+    use crate::ast::{Expr, Function};
+    use crate::bigint::BigInt;
+    use crate::complex::Complex;
+    use crate::decimal::Decimal;
+    use crate::functions::Builtin;
+    use crate::plugins;
+    use crate::time::{Date, Duration};
+    use crate::units::{self, Dimension, Quantity, Target};
+
+    #[derive(Debug, Clone)]
+    pub enum Value {
+        Number(f64),
+        /// an exact integer of any size
+        Integer(BigInt),
+        /// an integer shown in base 2, 8 or 16, like `hex(255)`
+        Radix(BigInt, u32),
+        /// a complex number with a nonzero imaginary part
+        Complex(Complex),
+        /// an exact decimal number, in decimal mode
+        Decimal(Decimal),
+        /// a number with a unit
+        Quantity(Quantity),
+        /// a quantity shown in a chosen unit, the result of a conversion
+        /// like `5 km to miles`
+        Converted(Quantity, Rc<Target>),
+        Date(Date),
+        /// a length of time, like the difference of two clock times
+        Duration(Duration),
+        List(Vec<Value>),
+        /// an expression with unknowns, in symbolic mode
+        Symbolic(Rc<Expr>),
+        /// rows of values under a header, like those of `truthtable`
+        Table(Rc<Table>),
+        Function(Rc<Closure>),
+        Builtin(&'static Builtin),
+        /// a function registered by the host application
+        Plugin(Rc<dyn plugins::Function>),
+    }
+
+    #[derive(Debug)]
+    pub struct Table {
+        pub header: Vec<String>,
+        pub rows: Vec<Vec<Value>>,
+    }
+
+    /// A function value along with the local variables it was created in.
+    #[derive(Debug)]
+    pub struct Closure {
+        pub function: Rc<Function>,
+        pub captured: HashMap<String, Value>,
+    }
+
+    impl Value {
+        pub fn as_number(&self) -> Result<f64, CalcError> {
+            match self {
+                Value::Number(number) => Ok(*number),
+                Value::Integer(integer) | Value::Radix(integer, _) => Ok(integer.to_f64()),
+                Value::Decimal(decimal) => Ok(decimal.to_f64()),
+                Value::Complex(_) => Err(CalcError::TypeMismatch("expected a real number, found a complex number")),
+                Value::Quantity(_) | Value::Converted(..) => Err(CalcError::TypeMismatch("expected a plain number, found a quantity with units")),
+                Value::Date(_) => Err(CalcError::TypeMismatch("expected a number, found a date")),
+                Value::Duration(_) => Err(CalcError::TypeMismatch("expected a plain number, found a duration")),
+                Value::List(_) => Err(CalcError::TypeMismatch("expected a number, found a list")),
+                Value::Symbolic(_) => Err(CalcError::TypeMismatch("expected a number, found a symbolic expression")),
+                Value::Table(_) => Err(CalcError::TypeMismatch("expected a number, found a table")),
+                Value::Function(_) | Value::Builtin(_) | Value::Plugin(_) => Err(CalcError::TypeMismatch("expected a number, found a function")),
+            }
+        }
+
+        pub fn as_complex(&self) -> Result<Complex, CalcError> {
+            match self {
+                Value::Number(number) => Ok(Complex::new(*number, 0.0)),
+                Value::Integer(integer) | Value::Radix(integer, _) => Ok(Complex::new(integer.to_f64(), 0.0)),
+                Value::Decimal(decimal) => Ok(Complex::new(decimal.to_f64(), 0.0)),
+                Value::Complex(z) => Ok(*z),
+                _ => Err(self.as_number().unwrap_err()),
+            }
+        }
+
+        /// Plain real numbers are dimensionless quantities, and durations
+        /// are times in seconds.
+        pub fn as_quantity(&self) -> Result<Quantity, CalcError> {
+            match self {
+                Value::Quantity(quantity) | Value::Converted(quantity, _) => Ok(*quantity),
+                Value::Duration(duration) => Ok(Quantity::new(duration.0, units::TIME)),
+                _ => Ok(Quantity::new(self.as_number()?, Dimension::NONE)),
+            }
+        }
+
+        /// The rows of a matrix, which is a list of lists all of the same
+        /// nonzero length.
+        pub fn as_matrix(&self) -> Option<Vec<&[Value]>> {
+            let rows = match self {
+                Value::List(rows) if ! rows.is_empty() => rows,
+                _ => return None,
+            };
+            let rows = rows.iter().map(|row| match row {
+                Value::List(row) if ! row.is_empty() => Some(row.as_slice()),
+                _ => None,
+            }).collect::<Option<Vec<_>>>()?;
+            rows.iter().all(|row| row.len() == rows[0].len()).then_some(rows)
+        }
+
+        /// The expression of a number or a symbolic value, to compute
+        /// with symbols.
+        pub fn to_expr(&self) -> Result<Expr, CalcError> {
+            match self {
+                Value::Number(number) => Ok(Expr::Number(*number)),
+                Value::Integer(integer) | Value::Radix(integer, _) => Ok(Expr::Integer(integer.clone())),
+                Value::Decimal(decimal) => Ok(Expr::Decimal(decimal.clone())),
+                Value::Symbolic(expr) => Ok((**expr).clone()),
+                _ => Err(CalcError::TypeMismatch("only real numbers can be combined with symbols")),
+            }
+        }
+
+        /// A symbolic value, or a number if no symbols are left.
+        pub fn from_expr(expr: Expr) -> Value {
+            match expr {
+                Expr::Number(number) => Value::Number(number),
+                expr => Value::Symbolic(Rc::new(expr)),
+            }
+        }
+
+        pub fn has_units(&self) -> bool {
+            matches!(self, Value::Quantity(_) | Value::Converted(..))
+        }
+
+        pub fn is_function(&self) -> bool {
+            matches!(self, Value::Function(_) | Value::Builtin(_) | Value::Plugin(_))
+        }
+    }
+
+    /// Quantities whose units cancel out become plain numbers.
+    impl From<Quantity> for Value {
+        fn from(quantity: Quantity) -> Self {
+            if quantity.dimension == Dimension::NONE {
+                Value::Number(quantity.value)
+            } else {
+                Value::Quantity(quantity)
+            }
+        }
+    }
+
+    /// Complex results without an imaginary part become real numbers.
+    impl From<Complex> for Value {
+        fn from(z: Complex) -> Self {
+            if z.im == 0.0 { Value::Number(z.re) } else { Value::Complex(z) }
+        }
+    }
+
+    impl fmt::Display for Value {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self {
+                Value::Number(number) => write!(f, "{}", number),
+                Value::Integer(integer) => write!(f, "{}", integer),
+                Value::Radix(integer, radix) => {
+                    let prefix = match radix {
+                        2 => "0b",
+                        8 => "0o",
+                        _ => "0x",
+                    };
+                    let digits = integer.abs().to_str_radix(*radix);
+                    write!(f, "{}{}{}", if integer.is_negative() { "-" } else { "" }, prefix, digits)
+                },
+                Value::Decimal(decimal) => fmt::Display::fmt(decimal, f),
+                Value::Quantity(quantity) => write!(f, "{} {}", quantity.value, quantity.dimension),
+                Value::Converted(quantity, target) => {
+                    write!(f, "{} {}", target.convert(*quantity).map_err(|_| fmt::Error)?, target)
+                },
+                Value::Date(date) => write!(f, "{}", date),
+                Value::Symbolic(expr) => write!(f, "{}", expr),
+                Value::Duration(duration) => write!(f, "{}", duration),
+                // the formatter is passed on, so that `{:.2}` pads every element
+                Value::List(items) => {
+                    write!(f, "[")?;
+                    for (i, item) in items.iter().enumerate() {
+                        if i > 0 {
+                            write!(f, ", ")?;
+                        }
+                        fmt::Display::fmt(item, f)?;
+                    }
+                    write!(f, "]")
+                },
+                Value::Complex(z) => write!(f, "{}", z),
+                Value::Function(closure) => write!(f, "<function({})>", closure.function.params.join(", ")),
+                Value::Builtin(builtin) => write!(f, "<builtin {}>", builtin.name),
+                Value::Plugin(function) => write!(f, "<builtin {}>", function.name()),
+                // columns as wide as their headings, the last one apart
+                Value::Table(table) => {
+                    let (result, inputs) = table.header.split_last().ok_or(fmt::Error)?;
+                    for heading in inputs {
+                        write!(f, "{} ", heading)?;
+                    }
+                    writeln!(f, "| {}", result)?;
+                    for (i, row) in table.rows.iter().enumerate() {
+                        for (heading, value) in inputs.iter().zip(row) {
+                            write!(f, "{:<width$} ", value.to_string(), width = heading.chars().count())?;
+                        }
+                        write!(f, "| {}", row.last().ok_or(fmt::Error)?)?;
+                        if i + 1 < table.rows.len() {
+                            writeln!(f)?;
+                        }
+                    }
+                    Ok(())
+                },
+            }
+        }
+    }
+}
+
+pub mod eval {
+    use std::cell::{Cell, RefCell};
+    use std::cmp::Ordering;
+    use std::collections::HashMap;
+    use std::rc::Rc;
+
+    use crate::{complex, math, functions, symbolic, CalcError};
+    use crate::bytecode::{Program, Scope};
+    use crate::functions::{Builtin, Call};
+    use crate::ast::{Expr, Function, Statement};
+    use crate::bigint::{BigInt, MAX_BITS};
+    use crate::complex::Complex;
+    use crate::decimal::Decimal;
+    use crate::numeric::Numeric;
+    use crate::operators::Operators;
+    use crate::plugins::Plugins;
+    use crate::random::Generator;
+    use crate::time::{Date, Duration, SECONDS_PER_DAY};
+    use crate::units::{self, Quantity, Target};
+    use crate::value::{Closure, Table, Value};
+
+    type Result<T> = std::result::Result<T, CalcError>;
+
+    /// How `n!` treats operands that are not non-negative integers.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum Factorial {
+        /// reject non-integer operands
+        Integer,
+        /// extend the factorial to real numbers as `gamma(n + 1)`
+        Gamma,
+    }
+
+    /// How integer-only operations like `&` treat non-integer operands.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum IntegerConversion {
+        /// reject non-integer operands
+        Strict,
+        /// round non-integer operands toward zero
+        Truncate,
+    }
+
+    /// Which remainder the `%` operator computes.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum Remainder {
+        /// the sign of the dividend, like C: `-7 % 3` is `-1`
+        Truncated,
+        /// never negative, like `mod`: `-7 % 3` is `2`
+        Euclidean,
+    }
+
+    impl Remainder {
+        pub fn apply(self, a: f64, b: f64) -> f64 {
+            match self {
+                Remainder::Truncated => a % b,
+                Remainder::Euclidean => a.rem_euclid(b),
+            }
+        }
+    }
+
+    /// The unit trigonometric functions measure angles in.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum Angle {
+        Radians,
+        Degrees,
+    }
+
+    impl Angle {
+        pub fn to_radians(self, angle: f64) -> f64 {
+            match self {
+                Angle::Radians => angle,
+                Angle::Degrees => angle.to_radians(),
+            }
+        }
+
+        /// Expresses an angle given in radians in this unit.
+        pub fn express(self, radians: f64) -> f64 {
+            match self {
+                Angle::Radians => radians,
+                Angle::Degrees => radians.to_degrees(),
+            }
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    pub struct Settings {
+        /// compute with exact decimals instead of binary floating point
+        pub decimal: bool,
+        /// round every result to this many decimal places, for amounts of
+        /// money; implies `decimal`
+        pub money: Option<u32>,
+        pub angle: Angle,
+        pub factorial: Factorial,
+        pub integer_conversion: IntegerConversion,
+        pub remainder: Remainder,
+        /// read `2(3+4)` as `2*(3+4)`
+        pub implicit_mul: bool,
+        /// keep unknown variables as symbols, so `2*x + 3*x` is `5*x`
+        pub symbolic: bool,
+        /// compute with integers modulo this, after `:mod p`
+        pub modulus: Option<BigInt>,
+        /// operators registered by an embedder
+        pub operators: Operators,
+    }
+
+    impl Default for Settings {
+        fn default() -> Self {
+            Settings {
+                decimal: false,
+                money: None,
+                angle: Angle::Radians,
+                factorial: Factorial::Integer,
+                integer_conversion: IntegerConversion::Strict,
+                remainder: Remainder::Truncated,
+                implicit_mul: true,
+                symbolic: false,
+                modulus: None,
+                operators: Operators::default(),
+            }
+        }
+    }
+
+    /// The state of one calculator: its variables and defined functions,
+    /// the functions registered by an embedder, the settings and the
+    /// random numbers. Contexts share nothing, so several calculators can
+    /// run side by side.
+    #[derive(Debug, Default)]
+    pub struct Context {
+        pub variables: HashMap<String, Value>,
+        pub functions: Plugins,
+        pub settings: Settings,
+        pub random: Generator,
+        memo: Memo,
+    }
+
+    /// A call of a user function, by the address of the function and the
+    /// normalized form of the values it is called with and the variables
+    /// it reads.
+    type MemoKey = (*const Function, String);
+
+    /// A result along with the functions it was computed by.
+    type MemoEntry = (Vec<Rc<Function>>, Value);
+
+    /// The results of calls of user functions during a session, so that a
+    /// function called again with the same arguments, like the `fib` of
+    /// `fib(n) = n < 2 ? n : fib(n - 1) + fib(n - 2)`, is computed once.
+    /// It is emptied when a statement assigns a variable or defines a
+    /// function, when the settings change and when it grows too large.
+    #[derive(Debug, Default)]
+    struct Memo {
+        /// the functions are kept alive along with the results, so that
+        /// their addresses in the keys are not reused
+        results: RefCell<HashMap<MemoKey, MemoEntry>>,
+        /// whether the call being evaluated drew random numbers or called
+        /// a function of the embedder, so that its result is not kept
+        impure: Cell<bool>,
+        /// the settings the results were computed with
+        settings: String,
+    }
+
+    // results kept at most, about as many as a plot computes
+    const MAX_MEMO: usize = 100_000;
+
+    impl Context {
+        pub fn new(settings: Settings) -> Self {
+            Context { settings, ..Context::default() }
+        }
+
+        /// Runs a statement, giving the value of an expression statement.
+        pub fn exec(&mut self, statement: &Statement) -> Result<Option<Value>> {
+            let settings = format!("{:?}", self.settings);
+            if settings != self.memo.settings {
+                self.memo = Memo { settings, ..Memo::default() };
+            }
+            let eval = |expr, context: &Context| {
+                let value = Evaluator::new(context).eval(expr)?;
+                Ok(match context.settings.money {
+                    Some(places) => round_money(value, places),
+                    None => value,
+                })
+            };
+            match statement {
+                Statement::Expr(expr) => eval(expr, self).map(Some),
+
+                Statement::Assign(name, expr) => {
+                    let value = eval(expr, self)?;
+                    self.variables.insert(name.clone(), value);
+                    self.memo.results.get_mut().clear();
+                    Ok(None)
+                },
+
+                Statement::Function(name, function) => {
+                    if self.functions.get(name).is_some() {
+                        return Err(CalcError::InvalidDefinition("cannot redefine a builtin function"));
+                    }
+                    // the body is brought into normal form once, instead of
+                    // being computed as written on every call
+                    let function = match self.settings.symbolic {
+                        true => Rc::new(Function {
+                            params: function.params.clone(),
+                            body: symbolic::simplify(&function.body),
+                        }),
+                        false => function.clone(),
+                    };
+                    let closure = Closure {
+                        function,
+                        captured: HashMap::new(),
+                    };
+                    self.variables.insert(name.clone(), Value::Function(Rc::new(closure)));
+                    self.memo.results.get_mut().clear();
+                    Ok(None)
+                },
+            }
+        }
+    }
+
+    fn factorial(n: f64, policy: Factorial) -> Result<f64> {
+        if n.fract() == 0.0 {
+            if n < 0.0 {
+                return Err(CalcError::Domain("factorial of a negative integer"));
+            }
+            // anything beyond 170! overflows f64 anyway
+            if n > 170.0 {
+                return Ok(f64::INFINITY);
+            }
+            return Ok((2..=n as u32).fold(1.0, |product, k| product * k as f64));
+        }
+
+        match policy {
+            Factorial::Integer => Err(CalcError::Domain("factorial of a non-integer")),
+            Factorial::Gamma => Ok(math::gamma(n + 1.0)),
+        }
+    }
+
+    pub fn to_integer(x: f64, conversion: IntegerConversion) -> Result<i64> {
+        let x = match conversion {
+            IntegerConversion::Strict if x.fract() != 0.0 => return Err(CalcError::TypeMismatch("operand is not an integer")),
+            IntegerConversion::Strict => x,
+            IntegerConversion::Truncate => x.trunc(),
+        };
+        // NaN and infinities fail this check as well
+        if x >= i64::MIN as f64 && x < i64::MAX as f64 {
+            Ok(x as i64)
+        } else {
+            Err(CalcError::Overflow("integer out of range"))
+        }
+    }
+
+    /// Bitwise operators work on 64-bit two's complement integers.
+    fn bitwise(operator: &str, op1: f64, op2: f64, conversion: IntegerConversion) -> Result<f64> {
+        Ok(integer_bitwise(operator, to_integer(op1, conversion)?, to_integer(op2, conversion)?)? as f64)
+    }
+
+    /// `bitwise` of exact integers, which are not rounded to floats on the
+    /// way, so all 64 bits count.
+    fn exact_bitwise(operator: &str, op1: &BigInt, op2: &BigInt) -> Result<BigInt> {
+        let integer = |x: &BigInt| x.to_i64().ok_or(CalcError::Overflow("integer out of range"));
+        Ok(BigInt::from(integer_bitwise(operator, integer(op1)?, integer(op2)?)?))
+    }
+
+    fn integer_bitwise(operator: &str, op1: i64, op2: i64) -> Result<i64> {
+        Ok(match operator {
+            "&" => op1 & op2,
+            "|" => op1 | op2,
+            "xor" => op1 ^ op2,
+            "<<" | ">>" => {
+                let shift = u32::try_from(op2).ok().filter(|shift| *shift < 64)
+                    .ok_or(CalcError::InvalidArguments("shift amount out of range"))?;
+                if operator == "<<" { op1 << shift } else { op1 >> shift }
+            },
+            _ => unreachable!(),
+        })
+    }
+
+    /// The quotient rounded towards negative infinity, so `-7 // 2` is
+    /// `-4`. It is derived from the exact remainder rather than by flooring
+    /// `a / b`, which may round up to the next integer first.
+    fn floor_div(a: f64, b: f64) -> f64 {
+        let remainder = a % b;
+        let mut quotient = (a - remainder) / b;
+        if remainder != 0.0 && (remainder < 0.0) != (b < 0.0) {
+            quotient -= 1.0;
+        }
+        quotient.round()
+    }
+
+    /// Calls the exact version of a builtin if all arguments are integers,
+    /// giving a decimal if one of them is, like `!` does.
+    fn exact_call(exact: functions::ExactFn, args: &[Value]) -> Option<Value> {
+        let integers = args.iter().map(|arg| match arg {
+            Value::Integer(integer) | Value::Radix(integer, _) => Some(integer.clone()),
+            Value::Decimal(decimal) => decimal.to_integer(),
+            _ => None,
+        }).collect::<Option<Vec<_>>>()?;
+        let result = exact(&integers)?;
+        match args.iter().any(|arg| matches!(arg, Value::Decimal(_))) {
+            true => Some(Value::Decimal(Decimal::from(result))),
+            false => Some(Value::Integer(result)),
+        }
+    }
+
+    /// `n!` for integers small enough to be computed exactly.
+    fn exact_factorial(n: &BigInt) -> Option<BigInt> {
+        const MAX_FACTORIAL: i64 = 5000;
+
+        let n = n.to_i64().filter(|n| (0..=MAX_FACTORIAL).contains(n))?;
+        Some((2..=n).fold(BigInt::from(1), |product, factor| &product * &BigInt::from(factor)))
+    }
+
+    /// Where zero based `index` falls in a list of `len` elements, negative
+    /// ones counting from the end.
+    fn position(index: f64, len: usize) -> Result<usize> {
+        if index.fract() != 0.0 {
+            return Err(CalcError::TypeMismatch("index is not an integer"));
+        }
+        let position = if index < 0.0 { len as f64 + index } else { index };
+        if (0.0..len as f64).contains(&position) { Ok(position as usize) } else { Err(CalcError::Domain("index out of range")) }
+    }
+
+    fn truth(value: bool) -> f64 {
+        if value { 1.0 } else { 0.0 }
+    }
+
+    /// The operators every kind of number has: the four basic ones and
+    /// comparisons. `None` leaves the operator to the rules of the kind,
+    /// as for `^`, inexact quotients or unordered operands.
+    fn arithmetic<T: Numeric>(operator: &str, percent: bool, op1: &T, op2: &T) -> Result<Option<Value>> {
+        let comparison = |test: fn(Ordering) -> bool| Ok(op1.compare(op2).map(|ordering| Value::Number(truth(test(ordering)))));
+        let result = match operator {
+            // `200 + 10%` adds ten percent of 200
+            "+" if percent => op1.add(&op1.mul(op2)),
+            "-" if percent => op1.sub(&op1.mul(op2)),
+            "+" => op1.add(op2),
+            "-" => op1.sub(op2),
+            "*" => op1.mul(op2),
+            "/" => match op1.div(op2)? {
+                Some(quotient) => quotient,
+                None => return Ok(None),
+            },
+            "<" => return comparison(Ordering::is_lt),
+            "<=" => return comparison(Ordering::is_le),
+            ">" => return comparison(Ordering::is_gt),
+            ">=" => return comparison(Ordering::is_ge),
+            "==" => return Ok(Some(Value::Number(truth(op1 == op2)))),
+            "!=" => return Ok(Some(Value::Number(truth(op1 != op2)))),
+            _ => return Ok(None),
+        };
+        Ok(Some(result.into_value()))
+    }
+
+    /// Applies a binary operator other than `&&` and `||` to real operands;
+    /// `percent` tells whether the right one is a percentage. `/` divides
+    /// by zero like IEEE 754 floats, giving `inf` or `NaN`, but `//` and
+    /// `%` fail.
+    pub(crate) fn real_binary(operator: &str, percent: bool, op1: f64, op2: f64, settings: &Settings) -> Result<f64> {
+        if let Some(result) = arithmetic(operator, percent, &op1, &op2)? {
+            return result.as_number();
+        }
+        if matches!(operator, "//" | "%") && op2 == 0.0 {
+            return Err(CalcError::DivisionByZero);
+        }
+        Ok(match operator {
+            "&" | "|" | "xor" | "<<" | ">>" => bitwise(operator, op1, op2, settings.integer_conversion)?,
+            "//" => floor_div(op1, op2),
+            "%" => settings.remainder.apply(op1, op2),
+            "^" => op1.powf(op2),
+            // NaN is unordered, and not less, equal or greater than anything
+            "<" | "<=" | ">" | ">=" => 0.0,
+            _ => unreachable!(),
+        })
+    }
+
+    /// The binary operators defined on complex numbers, used when either
+    /// operand is complex.
+    fn complex_binary(operator: &str, percent: bool, op1: Complex, op2: Complex) -> Result<Value> {
+        if let Some(result) = arithmetic(operator, percent, &op1, &op2)? {
+            return Ok(result);
+        }
+        match operator {
+            "^" => Ok(op1.pow(op2).into()),
+            _ => Err(CalcError::TypeMismatch("operator is not defined for complex numbers")),
+        }
+    }
+
+    /// The binary operators defined on quantities, used when either operand
+    /// has units; `+`, `-` and comparisons need matching units.
+    fn quantity_binary(operator: &str, percent: bool, op1: Quantity, op2: Quantity) -> Result<Value> {
+        let compare = |f: fn(&f64, &f64) -> bool| {
+            if op1.dimension != op2.dimension {
+                return Err(CalcError::TypeMismatch("incompatible units"));
+            }
+            Ok(Value::Number(truth(f(&op1.value, &op2.value))))
+        };
+        Ok(match operator {
+            // a percentage has no units, `5 m + 10%` is `5.5 m`
+            "+" if percent => op1.mul(Quantity::new(1.0 + op2.value, op2.dimension)),
+            "-" if percent => op1.mul(Quantity::new(1.0 - op2.value, op2.dimension)),
+            "+" => op1.add(op2, |a, b| a + b)?,
+            "-" => op1.add(op2, |a, b| a - b)?,
+            "*" => op1.mul(op2),
+            "/" => op1.div(op2),
+            "^" if op2.dimension == units::Dimension::NONE => op1.pow(op2.value)?,
+            "^" => return Err(CalcError::TypeMismatch("exponent has units")),
+            "<" => return compare(f64::lt),
+            "<=" => return compare(f64::le),
+            ">" => return compare(f64::gt),
+            ">=" => return compare(f64::ge),
+            "==" => return compare(f64::eq),
+            "!=" => return compare(f64::ne),
+            _ => return Err(CalcError::TypeMismatch("operator is not defined for quantities")),
+        }.into())
+    }
+
+    /// The binary operators on dates and durations, or `None` when neither
+    /// operand is one. Dates move by whole days and are a number of days
+    /// apart; durations compute like times in seconds.
+    fn temporal_binary(operator: &str, percent: bool, op1: &Value, op2: &Value) -> Option<Result<Value>> {
+        let days = |value: &Value| {
+            let quantity = value.as_quantity()?;
+            let days = quantity.value / SECONDS_PER_DAY;
+            if quantity.dimension != units::TIME {
+                return Err(CalcError::TypeMismatch("a date moves by a time like `3 days`"));
+            }
+            if days.fract() != 0.0 || days.abs() > 1e12 {
+                return Err(CalcError::TypeMismatch("a date moves by whole days"));
+            }
+            Ok(days as i64)
+        };
+        Some(match (operator, op1, op2) {
+            ("-", Value::Date(a), Value::Date(b)) => {
+                let apart = Quantity::new((a.0 - b.0) as f64 * SECONDS_PER_DAY, units::TIME);
+                Ok(Value::Converted(apart, Rc::new(Target(vec![("days".to_string(), 1)]))))
+            },
+            (_, Value::Date(a), Value::Date(b)) => {
+                let ordering = a.cmp(b);
+                Ok(Value::Number(truth(match operator {
+                    "<" => ordering.is_lt(),
+                    "<=" => ordering.is_le(),
+                    ">" => ordering.is_gt(),
+                    ">=" => ordering.is_ge(),
+                    "==" => ordering.is_eq(),
+                    "!=" => ordering.is_ne(),
+                    _ => return Some(Err(CalcError::TypeMismatch("operator is not defined for dates"))),
+                })))
+            },
+            ("+", Value::Date(date), other) | ("+", other, Value::Date(date)) => {
+                days(other).map(|days| Value::Date(Date(date.0 + days)))
+            },
+            ("-", Value::Date(date), other) => days(other).map(|days| Value::Date(Date(date.0 - days))),
+            (_, Value::Date(_), _) | (_, _, Value::Date(_)) => Err(CalcError::TypeMismatch("operator is not defined for dates")),
+            (_, Value::Duration(_), _) | (_, _, Value::Duration(_)) => {
+                let (op1, op2) = match (op1.as_quantity(), op2.as_quantity()) {
+                    (Ok(op1), Ok(op2)) => (op1, op2),
+                    (Err(msg), _) | (_, Err(msg)) => return Some(Err(msg)),
+                };
+                quantity_binary(operator, percent, op1, op2).map(|result| match result {
+                    Value::Quantity(quantity) if quantity.dimension == units::TIME => Value::Duration(Duration(quantity.value)),
+                    result => result,
+                })
+            },
+            _ => return None,
+        })
+    }
+
+    /// Whether a value is a number, which is written out exactly and so
+    /// can be part of a memo key.
+    fn is_plain(value: &Value) -> bool {
+        matches!(value, Value::Number(_) | Value::Integer(_) | Value::Radix(..) | Value::Decimal(_) | Value::Complex(_))
+    }
+
+    /// Adds the names `expr` refers to, as variables, functions or units
+    /// a variable may hide, to `names`.
+    fn mentions(expr: &Expr, names: &mut Vec<String>) {
+        let mut mention = |name: &String| if ! names.contains(name) { names.push(name.clone()) };
+        match expr {
+            Expr::Variable(name) | Expr::Quantity(_, name, _) => mention(name),
+            Expr::Call(name, _) => mention(name),
+            _ => (),
+        }
+        match expr {
+            Expr::Unary(_, oprand) | Expr::Postfix(_, oprand) | Expr::Quantity(oprand, ..) | Expr::Convert(oprand, _) => {
+                mentions(oprand, names);
+            },
+            Expr::Binary(_, op1, op2) | Expr::Index(op1, op2) | Expr::Let(_, op1, op2) => {
+                mentions(op1, names);
+                mentions(op2, names);
+            },
+            Expr::Call(_, args) | Expr::List(args) => args.iter().for_each(|arg| mentions(arg, names)),
+            Expr::Apply(callee, args) => {
+                mentions(callee, names);
+                args.iter().for_each(|arg| mentions(arg, names));
+            },
+            Expr::Slice(list, start, end) => {
+                mentions(list, names);
+                start.iter().chain(end).for_each(|bound| mentions(bound, names));
+            },
+            Expr::Conditional(condition, then, otherwise) => {
+                [condition, then, otherwise].into_iter().for_each(|expr| mentions(expr, names));
+            },
+            Expr::Lambda(function) => mentions(&function.body, names),
+            _ => (),
+        }
+    }
+
+    /// The roots found by `solve`: a single one is a number, several are a
+    /// list.
+    fn solutions(roots: Vec<f64>) -> Result<Value> {
+        match roots.as_slice() {
+            [] => Err(CalcError::NoSolution("no real solution found")),
+            [root] => Ok(Value::Number(*root)),
+            _ => Ok(Value::List(roots.into_iter().map(Value::Number).collect())),
+        }
+    }
+
+    /// The roots of a polynomial, real or complex, highest power first.
+    fn roots(coefficients: &[f64]) -> Value {
+        Value::List(complex::polynomial_roots(coefficients).into_iter().map(Value::from).collect())
+    }
+
+    /// The arithmetic operators on symbolic expressions, and `==` and `!=`
+    /// telling whether they simplify to the same expression, so `x + x == 2*x`
+    /// is true but `x == 1` is not, and neither is `sin(x)^2 + cos(x)^2 == 1`.
+    fn symbolic_binary(operator: &str, op1: &Value, op2: &Value) -> Result<Value> {
+        let operator = match operator {
+            "+" => "+",
+            "-" => "-",
+            "*" => "*",
+            "/" => "/",
+            "^" => "^",
+            "==" | "!=" => {
+                let same = symbolic::simplify(&op1.to_expr()?) == symbolic::simplify(&op2.to_expr()?);
+                return Ok(Value::Number(truth(same == (operator == "=="))));
+            },
+            "<" | "<=" | ">" | ">=" => return Err(CalcError::TypeMismatch("comparisons need numeric values")),
+            _ => return Err(CalcError::TypeMismatch("operator is not defined for symbolic expressions")),
+        };
+        let expr = Expr::Binary(operator, Box::new(op1.to_expr()?), Box::new(op2.to_expr()?));
+        Ok(Value::from_expr(symbolic::simplify(&expr)))
+    }
+
+    /// The binary operators with exact results on integers; the others,
+    /// like an inexact `/` or a negative `^`, give `None` and are left to
+    /// floating point. Dividing by zero is an error.
+    fn integer_binary(operator: &str, op1: &BigInt, op2: &BigInt, remainder: Remainder) -> Result<Option<Value>> {
+        if let Some(result) = arithmetic(operator, false, op1, op2)? {
+            return Ok(Some(result));
+        }
+        let result = match operator {
+            "//" | "%" => {
+                let (quotient, rest) = op1.divrem(op2).ok_or(CalcError::DivisionByZero)?;
+                match operator {
+                    "//" if ! rest.is_zero() && rest.is_negative() != op2.is_negative() => &quotient - &BigInt::from(1),
+                    "//" => quotient,
+                    _ if remainder == Remainder::Euclidean && rest.is_negative() => &rest + &op2.abs(),
+                    _ => rest,
+                }
+            },
+            "^" => match op2.to_i64().and_then(|exponent| u64::try_from(exponent).ok()) {
+                Some(exponent) if op1.bits().checked_mul(exponent).is_some_and(|bits| bits <= MAX_BITS) => op1.pow(exponent),
+                _ => return Ok(None),
+            },
+            "&" | "|" | "xor" | "<<" | ">>" => exact_bitwise(operator, op1, op2)?,
+            _ => return Ok(None),
+        };
+        Ok(Some(Value::Integer(result)))
+    }
+
+    /// The binary operators on integers modulo `modulus`, where `/`
+    /// multiplies by the inverse of the divisor and a negative `^` raises
+    /// the inverse. Exponents are not reduced, since `a^b` and `a^(b % p)`
+    /// differ.
+    fn modular_binary(operator: &str, op1: &BigInt, op2: &BigInt, modulus: &BigInt) -> Result<Option<Value>> {
+        let inverse = |x: &BigInt| match x.modulo(modulus).is_zero() {
+            true => Err(CalcError::DivisionByZero),
+            false => x.inverse_mod(modulus).ok_or(CalcError::Domain("no inverse for this modulus")),
+        };
+        let result = match operator {
+            "/" => op1 * &inverse(op2)?,
+            "^" if op2.is_negative() => inverse(op1)?.pow_mod(&- op2, modulus).unwrap(),
+            "^" => op1.pow_mod(op2, modulus).unwrap(),
+            _ => match integer_binary(operator, op1, op2, Remainder::Euclidean)? {
+                Some(Value::Integer(result)) => result,
+                result => return Ok(result),
+            },
+        };
+        Ok(Some(Value::Integer(result.modulo(modulus))))
+    }
+
+    /// The binary operators with exact results on decimals; the others,
+    /// like bitwise operators of fractions or a fractional `^`, give `None`
+    /// and are left to floating point.
+    fn decimal_binary(
+        operator: &str,
+        percent: bool,
+        op1: &Decimal,
+        op2: &Decimal,
+        remainder: Remainder,
+    ) -> Result<Option<Value>> {
+        if let Some(result) = arithmetic(operator, percent, op1, op2)? {
+            return Ok(Some(result));
+        }
+        let result = match operator {
+            "//" | "%" => {
+                let (quotient, rest) = op1.divrem(op2).ok_or(CalcError::DivisionByZero)?;
+                let inexact = rest != Decimal::from(0);
+                match operator {
+                    "//" if inexact && rest.is_negative() != op2.is_negative() => {
+                        Decimal::from(&quotient - &BigInt::from(1))
+                    },
+                    "//" => Decimal::from(quotient),
+                    _ if remainder == Remainder::Euclidean && rest.is_negative() => {
+                        if op2.is_negative() { &rest - op2 } else { &rest + op2 }
+                    },
+                    _ => rest,
+                }
+            },
+            "^" => match op2.to_integer().and_then(|n| n.to_i64()).and_then(|n| op1.pow(n)) {
+                Some(power) => power,
+                None => return Ok(None),
+            },
+            "&" | "|" | "xor" | "<<" | ">>" => match (op1.to_integer(), op2.to_integer()) {
+                (Some(a), Some(b)) => Decimal::from(exact_bitwise(operator, &a, &b)?),
+                _ => return Ok(None),
+            },
+            _ => return Ok(None),
+        };
+        Ok(Some(Value::Decimal(result)))
+    }
+
+    // deep enough for reasonable recursion, shallow enough for the stack
+    const MAX_DEPTH: usize = 200;
+
+    // terms of an indexed `sum` or `prod`
+    const MAX_TERMS: i64 = 10_000_000;
+
+    pub struct Evaluator<'a> {
+        settings: &'a Settings,
+        variables: &'a HashMap<String, Value>,
+        functions: &'a Plugins,
+        random: &'a Generator,
+        memo: &'a Memo,
+        /// parameters and captured variables of the function being evaluated
+        locals: HashMap<String, Value>,
+        depth: usize,
+    }
+
+    impl<'a> Evaluator<'a> {
+        pub fn new(context: &'a Context) -> Self {
+            Evaluator {
+                settings: &context.settings,
+                variables: &context.variables,
+                functions: &context.functions,
+                random: &context.random,
+                memo: &context.memo,
+                locals: HashMap::new(),
+                depth: 0,
+            }
+        }
+
+        /// Resolves a name to a local, a variable or a builtin function;
+        /// `i` is the imaginary unit and unit names like `m` are units,
+        /// unless they name something else.
+        fn lookup(&self, name: &str) -> Option<Value> {
+            self.locals.get(name).or_else(|| self.variables.get(name)).cloned()
+                .or_else(|| self.builtin(name))
+                .or_else(|| (name == "i").then_some(Value::Complex(Complex::I)))
+                .or_else(|| units::lookup(name).and_then(units::Unit::scale).map(Value::Quantity))
+        }
+
+        /// The builtin or registered function called `name`.
+        fn builtin(&self, name: &str) -> Option<Value> {
+            functions::lookup(name).map(Value::Builtin).or_else(|| self.functions.get(name).map(Value::Plugin))
+        }
+
+        /// Calls a function value with already evaluated arguments.
+        pub fn apply(&self, callee: &Value, mut args: Vec<Value>) -> Result<Value> {
+            // a list gives its elements to builtins of real numbers that take
+            // any number of them, like `sum([1, 2, 3])`
+            if let Value::Builtin(builtin @ Builtin { call: Call::Real(_), .. }) = callee {
+                if builtin.is_variadic() && args.iter().any(|arg| matches!(arg, Value::List(_))) {
+                    args = args.into_iter().flat_map(|arg| match arg {
+                        Value::List(items) => items,
+                        arg => vec![arg],
+                    }).collect();
+                }
+            }
+            let arity = match callee {
+                Value::Function(closure) => closure.function.params.len()..=closure.function.params.len(),
+                Value::Builtin(builtin) => builtin.arity.clone(),
+                Value::Plugin(function) => function.arity(),
+                _ => return Err(CalcError::NotAFunction),
+            };
+            if args.len() < *arity.start() {
+                return Err(CalcError::TooFewArguments);
+            }
+            if args.len() > *arity.end() {
+                return Err(CalcError::TooManyArguments);
+            }
+
+            match callee {
+                Value::Function(closure) => {
+                    if self.depth == MAX_DEPTH {
+                        return Err(CalcError::Overflow("maximum recursion depth exceeded"));
+                    }
+                    let key = self.memo_key(closure, &args);
+                    let cached = key.as_ref().and_then(|(key, _)| self.memo.results.borrow().get(key).map(|(_, result)| result.clone()));
+                    if let Some(result) = cached {
+                        return Ok(result);
+                    }
+                    let mut locals = closure.captured.clone();
+                    locals.extend(closure.function.params.iter().cloned().zip(args));
+                    let evaluator = Evaluator {
+                        locals,
+                        depth: self.depth + 1,
+                        ..*self
+                    };
+                    // an impure call makes the calls around it impure too
+                    let outer = self.memo.impure.replace(false);
+                    let result = evaluator.eval(&closure.function.body);
+                    let impure = self.memo.impure.get();
+                    self.memo.impure.set(outer || impure);
+                    if let (Some((key, functions)), Ok(value), false) = (key, &result, impure) {
+                        if is_plain(value) {
+                            let mut results = self.memo.results.borrow_mut();
+                            if results.len() == MAX_MEMO {
+                                results.clear();
+                            }
+                            results.insert(key, (functions, value.clone()));
+                        }
+                    }
+                    result
+                },
+
+                Value::Builtin(builtin) => {
+                    let call = match builtin.call {
+                        Call::Values(call) => return call(&args, self.settings),
+                        Call::Random(call) => {
+                            self.memo.impure.set(true);
+                            let args = args.iter().map(Value::as_number).collect::<Result<Vec<_>>>()?;
+                            return call(&args, self.random).map(Value::Number);
+                        },
+                        Call::Real(call) => call,
+                    };
+                    if let [Value::List(items)] = args.as_slice() {
+                        return items.iter().map(|item| self.apply(callee, vec![item.clone()])).collect::<Result<_>>()
+                            .map(Value::List);
+                    }
+                    // a function of symbols is left as it is, like `sin(x)`
+                    if args.iter().any(|arg| matches!(arg, Value::Symbolic(_))) {
+                        let args = args.iter().map(Value::to_expr).collect::<Result<_>>()?;
+                        return Ok(Value::from_expr(symbolic::simplify(&Expr::Call(builtin.name.to_string(), args))));
+                    }
+                    // integers stay exact where the real version would round
+                    // them, like `fact(25)`
+                    if let Some(exact) = functions::exact(builtin.name) {
+                        if let Some(result) = exact_call(exact, &args) {
+                            return Ok(result);
+                        }
+                    }
+                    // the complex version takes over for complex arguments
+                    // and where the real one has no result, like `sqrt(-4)`
+                    if let Some(complex) = functions::complex(builtin.name) {
+                        let z = args[0].as_complex()?;
+                        if z.im == 0.0 {
+                            if let Ok(result) = call(&[z.re], self.settings) {
+                                return Ok(Value::Number(result));
+                            }
+                        }
+                        return complex(z).map(Value::from);
+                    }
+                    let args = args.iter().map(Value::as_number).collect::<Result<Vec<_>>>()?;
+                    call(&args, self.settings).map(Value::Number)
+                },
+
+                Value::Plugin(function) => {
+                    self.memo.impure.set(true);
+                    function.call(&args)
+                },
+
+                _ => unreachable!(),
+            }
+        }
+
+        /// What a call of `closure` is kept under: the function, and its
+        /// arguments and the variables it reads in a form telling integers
+        /// from floats, along with the functions it reaches; `None` if one
+        /// of them is not a plain number.
+        fn memo_key(&self, closure: &Closure, args: &[Value]) -> Option<(MemoKey, Vec<Rc<Function>>)> {
+            if ! args.iter().all(is_plain) {
+                return None;
+            }
+            let mut functions = Vec::new();
+            let mut globals = Vec::new();
+            let captured = self.reads(closure, &mut functions, &mut globals)?;
+            // symbolic evaluation gives other results for unknowns
+            let values = format!("{:?}; {}; {}; {}", args, captured, globals.join(", "), self.settings.symbolic);
+            Some(((Rc::as_ptr(&closure.function), values), functions))
+        }
+
+        /// The captured variables of `closure` written out, adding the
+        /// global variables its body names to `globals`, and for functions
+        /// among them, what they read in turn; `None` if one of them is
+        /// neither a plain number nor a function. Names that are not
+        /// variables count too, since they may be defined later.
+        fn reads(&self, closure: &Closure, functions: &mut Vec<Rc<Function>>, globals: &mut Vec<String>) -> Option<String> {
+            if ! closure.captured.values().all(is_plain) {
+                return None;
+            }
+            let mut captured: Vec<String> = closure.captured.iter().map(|(name, value)| format!("{} = {:?}", name, value)).collect();
+            captured.sort();
+            if functions.iter().any(|function| Rc::ptr_eq(function, &closure.function)) {
+                return Some(captured.join(", "));
+            }
+            functions.push(closure.function.clone());
+            let mut names = Vec::new();
+            mentions(&closure.function.body, &mut names);
+            for name in names {
+                if closure.function.params.contains(&name) || closure.captured.contains_key(&name) {
+                    continue;
+                }
+                let global = match self.variables.get(&name) {
+                    None => format!("{} unset", name),
+                    Some(value) if is_plain(value) => format!("{} = {:?}", name, value),
+                    Some(Value::Function(function)) => {
+                        let captured = self.reads(function, functions, globals)?;
+                        format!("{} = {:p} [{}]", name, Rc::as_ptr(&function.function), captured)
+                    },
+                    Some(_) => return None,
+                };
+                globals.push(global);
+            }
+            Some(captured.join(", "))
+        }
+
+        /// Whether a call is to a builtin that takes its arguments
+        /// unevaluated, like `diff`, or `sum` over an index like in
+        /// `sum(k, 1, 10, k^2)`; a function of the same name hides it.
+        fn is_special(&self, name: &str, args: &[Expr]) -> bool {
+            let special = match name {
+                "diff" | "simplify" | "solve" | "roots" | "integrate" | "nderiv" | "divmod" => true,
+                "map" | "filter" | "reduce" | "truthtable" => true,
+                // a variable first of four arguments is the index, even if
+                // the term does not use it or it has a value
+                "sum" | "prod" => matches!(args, [Expr::Variable(_), _, _, _]),
+                _ => false,
+            };
+            special && ! self.locals.get(name).or_else(|| self.variables.get(name)).is_some_and(Value::is_function)
+        }
+
+        /// Evaluates `expr` with `name`, if any, and unknown variables as
+        /// symbols.
+        fn eval_symbolic(&self, expr: &Expr, name: Option<&str>) -> Result<Expr> {
+            let settings = Settings { symbolic: true, ..self.settings.clone() };
+            let mut locals = self.locals.clone();
+            if let Some(name) = name {
+                locals.insert(name.to_string(), Value::Symbolic(Rc::new(Expr::Variable(name.to_string()))));
+            }
+            let evaluator = Evaluator {
+                settings: &settings,
+                locals,
+                ..*self
+            };
+            evaluator.eval(expr)?.to_expr()
+        }
+
+        fn special(&self, name: &str, args: &[Expr]) -> Result<Value> {
+            match (name, args) {
+                ("diff", [expr, Expr::Variable(variable)]) => {
+                    let derivative = symbolic::derivative(&self.eval_symbolic(expr, Some(variable))?, variable)?;
+                    Ok(Value::from_expr(symbolic::simplify(&derivative)))
+                },
+                ("diff", [_, _]) => Err(CalcError::InvalidArguments("expected a variable to differentiate by")),
+                ("diff", _) => Err(CalcError::InvalidArguments("diff takes an expression and a variable")),
+                ("simplify", [expr]) => Ok(Value::from_expr(symbolic::simplify(&self.eval_symbolic(expr, None)?))),
+                ("simplify", _) => Err(CalcError::InvalidArguments("simplify takes one expression")),
+                ("solve", [Expr::Binary("==", lhs, rhs), Expr::Variable(variable)]) => {
+                    self.solve(&Expr::Binary("-", lhs.clone(), rhs.clone()), variable)
+                },
+                ("solve", [expr, Expr::Variable(variable)]) => self.solve(expr, variable),
+                ("solve", [_, _]) => Err(CalcError::InvalidArguments("expected a variable to solve for")),
+                ("solve", _) => Err(CalcError::InvalidArguments("solve takes an equation and a variable")),
+                ("roots", [expr, Expr::Variable(variable)]) => {
+                    let expr = symbolic::simplify(&self.eval_symbolic(expr, Some(variable))?);
+                    let mut coefficients = symbolic::polynomial(&expr, variable).ok_or(CalcError::InvalidArguments("not a polynomial"))?;
+                    coefficients.reverse();
+                    Ok(roots(&coefficients))
+                },
+                ("roots", [_, _]) => Err(CalcError::InvalidArguments("expected the variable of the polynomial")),
+                ("roots", [coefficients]) => match self.eval(coefficients)? {
+                    Value::List(coefficients) => {
+                        Ok(roots(&coefficients.iter().map(Value::as_number).collect::<Result<Vec<_>>>()?))
+                    },
+                    _ => Err(CalcError::InvalidArguments("expected a list of coefficients")),
+                },
+                ("roots", _) => Err(CalcError::InvalidArguments("roots takes a list of coefficients or a polynomial and its variable")),
+                ("integrate", [expr, Expr::Variable(variable), bounds @ ..]) if matches!(bounds.len(), 2 | 3) => {
+                    let (a, b) = (self.eval_number(&bounds[0])?, self.eval_number(&bounds[1])?);
+                    let tolerance = bounds.get(2).map_or(Ok(1e-10), |tolerance| self.eval_number(tolerance))?;
+                    if tolerance <= 0.0 {
+                        return Err(CalcError::InvalidArguments("tolerance must be positive"));
+                    }
+                    if ! a.is_finite() || ! b.is_finite() {
+                        return Err(CalcError::InvalidArguments("bounds must be finite"));
+                    }
+                    let mut error = None;
+                    let f = self.real_function(expr, variable);
+                    let f = |x| match f(x) {
+                        Ok(y) if y.is_finite() => Some(y),
+                        Ok(_) => {
+                            error = Some(CalcError::Domain("integrand is not finite on the interval"));
+                            None
+                        },
+                        Err(msg) => {
+                            error = Some(msg);
+                            None
+                        },
+                    };
+                    match math::integrate(f, a, b, tolerance) {
+                        Some(integral) if integral.is_finite() => Ok(Value::Number(math::significant(integral))),
+                        Some(_) => Err(CalcError::Overflow("integral is not finite")),
+                        None => Err(error.unwrap()),
+                    }
+                },
+                ("integrate", [_, _, ..]) if matches!(args.len(), 4 | 5) => Err(CalcError::InvalidArguments("expected a variable to integrate by")),
+                ("integrate", _) => Err(CalcError::InvalidArguments("integrate takes an expression, a variable, two bounds and a tolerance")),
+                // the five point central difference, whose step balances the
+                // error of the formula against rounding errors
+                ("nderiv", [expr, Expr::Variable(variable), at]) => {
+                    let x = self.eval_number(at)?;
+                    let h = 2e-4 * (1.0 + x.abs());
+                    let f = self.real_function(expr, variable);
+                    let f = |dx: f64| f(x + dx * h);
+                    let derivative = (f(-2.0)? - 8.0 * f(-1.0)? + 8.0 * f(1.0)? - f(2.0)?) / (12.0 * h);
+                    Ok(Value::Number(math::significant(derivative)))
+                },
+                ("nderiv", [_, _, _]) => Err(CalcError::InvalidArguments("expected a variable to differentiate by")),
+                ("nderiv", _) => Err(CalcError::InvalidArguments("nderiv takes an expression, a variable and a point")),
+                ("sum" | "prod", [Expr::Variable(index), from, to, body]) => {
+                    let bound = |expr| {
+                        let bound = self.eval_number(expr)?;
+                        if bound.fract() != 0.0 || bound.abs() > 1e15 {
+                            return Err(CalcError::InvalidArguments("bounds must be integers"));
+                        }
+                        Ok(bound as i64)
+                    };
+                    let (from, to) = (bound(from)?, bound(to)?);
+                    if to.saturating_sub(from) >= MAX_TERMS {
+                        return Err(CalcError::Overflow("too many terms"));
+                    }
+                    let (operator, identity) = if name == "sum" { ("+", 0) } else { ("*", 1) };
+                    let integer = |n: i64| match self.settings.decimal {
+                        true => Value::Decimal(Decimal::from(n)),
+                        false => Value::Integer(BigInt::from(n)),
+                    };
+                    let body = body.fold_constants(self.settings);
+                    (from..=to).try_fold(integer(identity), |result, k| {
+                        let mut locals = self.locals.clone();
+                        locals.insert(index.clone(), integer(k));
+                        let term = Evaluator { locals, ..*self }.eval(&body)?;
+                        self.binary(operator, false, result, term)
+                    })
+                },
+                // the quotient rounded down like `//`, and what remains of `a`
+                ("divmod", [a, b]) => {
+                    let (a, b) = (self.eval(a)?, self.eval(b)?);
+                    let quotient = self.binary("//", false, a.clone(), b.clone())?;
+                    let remainder = self.binary("-", false, a, self.binary("*", false, b, quotient.clone())?)?;
+                    Ok(Value::List(vec![quotient, remainder]))
+                },
+                ("divmod", _) => Err(CalcError::InvalidArguments("divmod takes a dividend and a divisor")),
+                ("map", [function, list]) => {
+                    let (function, items) = (self.eval(function)?, self.eval_items(list, CalcError::TypeMismatch("expected a list"))?);
+                    items.into_iter().map(|item| self.apply(&function, vec![item])).collect::<Result<_>>().map(Value::List)
+                },
+                ("map", _) => Err(CalcError::InvalidArguments("map takes a function and a list")),
+                ("filter", [predicate, list]) => {
+                    let (predicate, items) = (self.eval(predicate)?, self.eval_items(list, CalcError::TypeMismatch("expected a list"))?);
+                    let mut kept = Vec::new();
+                    for item in items {
+                        if self.apply(&predicate, vec![item.clone()])?.as_number()? != 0.0 {
+                            kept.push(item);
+                        }
+                    }
+                    Ok(Value::List(kept))
+                },
+                ("filter", _) => Err(CalcError::InvalidArguments("filter takes a predicate and a list")),
+                ("reduce", [function, initial, list]) => {
+                    let (function, initial, items) = (self.eval(function)?, self.eval(initial)?, self.eval_items(list, CalcError::TypeMismatch("expected a list"))?);
+                    items.into_iter().try_fold(initial, |result, item| self.apply(&function, vec![result, item]))
+                },
+                ("reduce", _) => Err(CalcError::InvalidArguments("reduce takes a function, an initial value and a list")),
+                // rows of every assignment of 0 and 1 to the unknown
+                // variables, counting up in binary
+                ("truthtable", [expr]) => {
+                    const MAX_VARIABLES: usize = 12;
+
+                    let variables: Vec<String> = symbolic::variables(expr).into_iter()
+                        .filter(|name| self.locals.get(name).or_else(|| self.variables.get(name)).is_none())
+                        .collect();
+                    if variables.len() > MAX_VARIABLES {
+                        return Err(CalcError::Overflow("too many variables for a truth table"));
+                    }
+                    let folded = expr.fold_constants(self.settings);
+                    let rows = (0..1u32 << variables.len()).map(|row| {
+                        let mut locals = self.locals.clone();
+                        let inputs: Vec<Value> = (0..variables.len())
+                            .map(|i| Value::Number((row >> (variables.len() - 1 - i) & 1) as f64))
+                            .collect();
+                        locals.extend(variables.iter().cloned().zip(inputs.iter().cloned()));
+                        let result = Evaluator { locals, ..*self }.eval(&folded)?;
+                        Ok(inputs.into_iter().chain([result]).collect())
+                    }).collect::<Result<_>>()?;
+                    let header = variables.into_iter().chain([expr.to_string()]).collect();
+                    Ok(Value::Table(Rc::new(Table { header, rows })))
+                },
+                ("truthtable", _) => Err(CalcError::InvalidArguments("truthtable takes one expression")),
+                _ => unreachable!(),
+            }
+        }
+
+        /// Evaluates `expr` as a real function of `name` at `x`.
+        fn eval_at(&self, expr: &Expr, name: &str, x: f64) -> Result<f64> {
+            let mut locals = self.locals.clone();
+            locals.insert(name.to_string(), Value::Number(x));
+            Evaluator { locals, ..*self }.eval(expr)?.as_number()
+        }
+
+        /// `expr` as a real function of `name`, for computing it at many
+        /// points: compiled to bytecode where possible, with the points
+        /// where the program fails evaluated again for the error.
+        fn real_function<'e>(&'e self, expr: &'e Expr, name: &'e str) -> impl Fn(f64) -> Result<f64> + 'e {
+            let program = Program::compile(expr, &[name], self);
+            move |x| match program.as_ref().map(|program| program.run(&[x], self.settings)) {
+                Some(Ok(y)) => Ok(y),
+                _ => self.eval_at(expr, name, x),
+            }
+        }
+
+        /// The real values of `name` where `expr` is zero: exact ones for
+        /// linear and quadratic equations, and otherwise those found
+        /// numerically. A single root is a number, several are a list.
+        fn solve(&self, expr: &Expr, name: &str) -> Result<Value> {
+            let expr = symbolic::simplify(&self.eval_symbolic(expr, Some(name))?);
+            let roots = match symbolic::polynomial(&expr, name).as_deref() {
+                Some([]) => return Err(CalcError::NoSolution("every value is a solution")),
+                Some([_]) => return Err(CalcError::NoSolution("no solution")),
+                Some([c, b]) => vec![- c / b],
+                Some([c, b, a]) => {
+                    let discriminant = b * b - 4.0 * a * c;
+                    if discriminant < 0.0 {
+                        vec![]
+                    } else if discriminant == 0.0 {
+                        vec![- b / (2.0 * a)]
+                    } else {
+                        // avoids cancellation between `-b` and the root
+                        let q = -0.5 * (b + b.signum() * discriminant.sqrt());
+                        let mut roots = if q == 0.0 { vec![0.0] } else { vec![q / a, c / q] };
+                        roots.sort_by(f64::total_cmp);
+                        roots
+                    }
+                },
+                _ => {
+                    // other unknowns stay symbols rather than being errors
+                    let settings = Settings { symbolic: true, ..self.settings.clone() };
+                    // a program has no unknowns, they keep it from compiling
+                    let scope = Evaluator { settings: &settings, locals: self.locals.clone(), ..*self };
+                    if let Some(program) = Program::compile(&expr, &[name], &scope) {
+                        return solutions(math::find_roots(|x| program.run(&[x], &settings).ok()));
+                    }
+                    let mut unknowns = false;
+                    let roots = math::find_roots(|x| {
+                        let mut locals = self.locals.clone();
+                        locals.insert(name.to_string(), Value::Number(x));
+                        let evaluator = Evaluator { settings: &settings, locals, ..*self };
+                        match evaluator.eval(&expr) {
+                            Ok(Value::Symbolic(_)) => {
+                                unknowns = true;
+                                None
+                            },
+                            value => value.and_then(|value| value.as_number()).ok(),
+                        }
+                    });
+                    if unknowns {
+                        return Err(CalcError::NoSolution("the equation has other unknowns"));
+                    }
+                    roots
+                },
+            };
+            solutions(roots)
+        }
+
+        fn eval_number(&self, expr: &Expr) -> Result<f64> {
+            self.eval(expr)?.as_number()
+        }
+
+        /// Evaluates `expr` to the items of a list, failing with `error`
+        /// for any other value.
+        fn eval_items(&self, expr: &Expr, error: CalcError) -> Result<Vec<Value>> {
+            match self.eval(expr)? {
+                Value::List(items) => Ok(items),
+                _ => Err(error),
+            }
+        }
+
+        pub fn eval(&self, expr: &Expr) -> Result<Value> {
+            match expr {
+                Expr::Variable(name) => self.lookup(name)
+                    .or_else(|| self.settings.symbolic.then(|| Value::Symbolic(Rc::new(expr.clone()))))
+                    .ok_or(CalcError::UnknownIdentifier),
+
+                Expr::Lambda(function) => Ok(Value::Function(Rc::new(Closure {
+                    function: function.clone(),
+                    captured: self.locals.clone(),
+                }))),
+
+                Expr::Call(name, args) if self.is_special(name, args) => self.special(name, args),
+
+                Expr::Call(name, args) => {
+                    // a variable that is not a function does not hide the
+                    // builtin of the same name
+                    let callee = match self.lookup(name) {
+                        Some(callee) if callee.is_function() => callee,
+                        Some(_) => self.builtin(name).ok_or(CalcError::NotAFunction)?,
+                        None => return Err(CalcError::UnknownFunction),
+                    };
+                    let args = args.iter().map(|arg| self.eval(arg)).collect::<Result<Vec<_>>>()?;
+                    self.apply(&callee, args)
+                },
+
+                Expr::Apply(callee, args) => {
+                    let callee = self.eval(callee)?;
+                    let args = args.iter().map(|arg| self.eval(arg)).collect::<Result<Vec<_>>>()?;
+                    self.apply(&callee, args)
+                },
+
+                Expr::List(items) => items.iter().map(|item| self.eval(item)).collect::<Result<_>>().map(Value::List),
+
+                Expr::Index(list, index) => {
+                    let items = self.eval_items(list, CalcError::TypeMismatch("only lists can be indexed"))?;
+                    let index = position(self.eval_number(index)?, items.len())?;
+                    items.into_iter().nth(index).ok_or(CalcError::Domain("index out of range"))
+                },
+
+                Expr::Slice(list, start, end) => {
+                    let items = self.eval_items(list, CalcError::TypeMismatch("only lists can be indexed"))?;
+                    // a bound may also be just past the end
+                    let bound = |bound: &Option<Box<Expr>>, default| match bound {
+                        Some(bound) => match self.eval_number(bound)? {
+                            index if index < 0.0 => position(index, items.len()),
+                            index => position(index, items.len() + 1),
+                        },
+                        None => Ok(default),
+                    };
+                    let (start, end) = (bound(start, 0)?, bound(end, items.len())?);
+                    Ok(Value::List(items.get(start..end.max(start)).unwrap_or_default().to_vec()))
+                },
+
+                Expr::Let(name, value, body) => {
+                    let mut locals = self.locals.clone();
+                    locals.insert(name.clone(), self.eval(value)?);
+                    let evaluator = Evaluator {
+                        locals,
+                        ..*self
+                    };
+                    evaluator.eval(body)
+                },
+
+                Expr::Conditional(condition, then, otherwise) => {
+                    if self.eval_number(condition)? != 0.0 {
+                        self.eval(then)
+                    } else {
+                        self.eval(otherwise)
+                    }
+                },
+
+                Expr::Decimal(decimal) => Ok(Value::Decimal(decimal.clone())),
+
+                Expr::Date(date) => Ok(Value::Date(*date)),
+
+                Expr::Duration(duration) => Ok(Value::Duration(*duration)),
+
+                Expr::Integer(integer) if self.settings.decimal => Ok(Value::Decimal(Decimal::from(integer.clone()))),
+                Expr::Integer(integer) => Ok(Value::Integer(integer.clone())),
+
+                Expr::Number(number) if self.settings.decimal => {
+                    Ok(Decimal::from_f64(*number).map_or(Value::Number(*number), Value::Decimal))
+                },
+
+                Expr::Unary(operator @ ("+" | "-"), oprand) => {
+                    let oprand = self.eval(oprand)?;
+                    self.unary(operator, oprand)
+                },
+
+                Expr::Unary(operator, oprand) if self.settings.operators.unary(operator).is_some() => {
+                    let oprand = self.eval(oprand)?;
+                    (self.settings.operators.unary(operator).unwrap().eval)(oprand)
+                },
+
+                Expr::Binary(operator, op1, op2) if self.settings.operators.binary(operator).is_some() => {
+                    let op1 = self.eval(op1)?;
+                    let op2 = self.eval(op2)?;
+                    (self.settings.operators.binary(operator).unwrap().eval)(op1, op2)
+                },
+
+                Expr::Binary("|>", value, function) => {
+                    let value = self.eval(value)?;
+                    self.apply(&self.eval(function)?, vec![value])
+                },
+
+                Expr::Binary(operator, op1, op2) if ! matches!(*operator, "&&" | "||") => {
+                    let percent = matches!(**op2, Expr::Postfix("%", _));
+                    let op1 = self.eval(op1)?;
+                    let op2 = self.eval(op2)?;
+                    self.binary(operator, percent, op1, op2)
+                },
+
+                Expr::Quantity(number, name, exponent) => {
+                    let number = self.eval(number)?;
+                    // a variable named like the unit is multiplied instead
+                    match self.locals.get(name).or_else(|| self.variables.get(name)) {
+                        Some(variable) => {
+                            let power = match exponent {
+                                1 => variable.clone(),
+                                _ => self.binary("^", false, variable.clone(), Value::Integer(BigInt::from(*exponent as i64)))?,
+                            };
+                            self.binary("*", false, number, power)
+                        },
+                        None => {
+                            let unit = units::lookup(name).ok_or(CalcError::UnknownUnit)?;
+                            Ok(unit.quantity(number.as_number()?, *exponent)?.into())
+                        },
+                    }
+                },
+
+                Expr::Convert(expr, target) => convert(self.eval(expr)?, target),
+
+                Expr::Postfix("!", oprand) => {
+                    let oprand = self.eval(oprand)?;
+                    let exact = match &oprand {
+                        Value::Integer(n) => exact_factorial(n).map(Value::Integer),
+                        Value::Decimal(n) => n.to_integer().as_ref().and_then(exact_factorial)
+                            .map(|n| Value::Decimal(Decimal::from(n))),
+                        _ => None,
+                    };
+                    match exact {
+                        Some(result) => Ok(result),
+                        None => factorial(oprand.as_number()?, self.settings.factorial).map(Value::Number),
+                    }
+                },
+
+                Expr::Postfix("%", oprand) => match self.eval(oprand)? {
+                    Value::Decimal(decimal) => Ok(Value::Decimal(decimal.shift(-2))),
+                    oprand => Ok(Value::Number(oprand.as_number()? / 100.0)),
+                },
+
+                _ => self.eval_arithmetic(expr).map(Value::Number),
+            }
+        }
+
+        /// Applies a prefix `+` or `-`.
+        fn unary(&self, operator: &str, oprand: Value) -> Result<Value> {
+            match oprand {
+                Value::Symbolic(expr) => {
+                    let operator = if operator == "-" { "-" } else { "+" };
+                    Ok(Value::from_expr(symbolic::simplify(&Expr::Unary(operator, Box::new((*expr).clone())))))
+                },
+                Value::List(items) => items.into_iter().map(|item| self.unary(operator, item)).collect::<Result<_>>()
+                    .map(Value::List),
+                Value::Complex(z) if operator == "-" => Ok(Value::Complex(- z)),
+                Value::Integer(integer) if operator == "-" => Ok(Value::Integer(- &integer)),
+                Value::Radix(integer, radix) if operator == "-" => Ok(Value::Radix(- &integer, radix)),
+                Value::Decimal(decimal) if operator == "-" => Ok(Value::Decimal(- &decimal)),
+                Value::Duration(duration) if operator == "-" => Ok(Value::Duration(Duration(- duration.0))),
+                oprand if oprand.has_units() => {
+                    let quantity = oprand.as_quantity()?;
+                    let sign = if operator == "-" { -1.0 } else { 1.0 };
+                    Ok(Value::Quantity(Quantity::new(sign * quantity.value, quantity.dimension)))
+                },
+                oprand @ (Value::Complex(_) | Value::Integer(_) | Value::Radix(..) | Value::Decimal(_) | Value::Duration(_)) => {
+                    Ok(oprand)
+                },
+                oprand => {
+                    let oprand = oprand.as_number()?;
+                    Ok(Value::Number(if operator == "-" { - oprand } else { oprand }))
+                },
+            }
+        }
+
+        /// Applies a binary operator other than `&&` and `||`, in the most
+        /// exact arithmetic both operands allow.
+        fn binary(&self, operator: &str, percent: bool, op1: Value, op2: Value) -> Result<Value> {
+            if operator == "*" && (op1.as_matrix().is_some() || op2.as_matrix().is_some()) {
+                if let (Value::List(_), Value::List(_)) = (&op1, &op2) {
+                    return self.matrix_product(&op1, &op2);
+                }
+            }
+            // lists compute elementwise, with a single value for each element
+            match (op1, op2) {
+                (Value::List(items1), Value::List(items2)) => {
+                    if items1.len() != items2.len() {
+                        return Err(CalcError::TypeMismatch("lists differ in length"));
+                    }
+                    items1.into_iter().zip(items2).map(|(item1, item2)| self.binary(operator, percent, item1, item2))
+                        .collect::<Result<_>>().map(Value::List)
+                },
+                (Value::List(items), op2) => items.into_iter().map(|item| self.binary(operator, percent, item, op2.clone()))
+                    .collect::<Result<_>>().map(Value::List),
+                (op1, Value::List(items)) => items.into_iter().map(|item| self.binary(operator, percent, op1.clone(), item))
+                    .collect::<Result<_>>().map(Value::List),
+                (op1, op2) => self.scalar_binary(operator, percent, op1, op2),
+            }
+        }
+
+        /// The matrix product, where a list of numbers on the left is a row
+        /// and on the right a column, and the result is a list again.
+        fn matrix_product(&self, op1: &Value, op2: &Value) -> Result<Value> {
+            let row = op1.as_matrix().is_none();
+            let column = op2.as_matrix().is_none();
+            let wrap = |value: &Value, column| match value {
+                Value::List(items) if column => Value::List(items.iter().map(|item| Value::List(vec![item.clone()])).collect()),
+                Value::List(items) => Value::List(vec![Value::List(items.clone())]),
+                _ => unreachable!(),
+            };
+            let (op1, op2) = (if row { wrap(op1, false) } else { op1.clone() }, if column { wrap(op2, true) } else { op2.clone() });
+            let (rows, columns) = match (op1.as_matrix(), op2.as_matrix()) {
+                (Some(rows), Some(columns)) => (rows, columns),
+                _ => return Err(CalcError::TypeMismatch("matrix rows differ in length")),
+            };
+            if rows[0].len() != columns.len() {
+                return Err(CalcError::TypeMismatch("matrix shapes do not match for a product"));
+            }
+            let entry = |row: &[Value], j: usize| {
+                let mut terms = row.iter().zip(&columns).map(|(item, column)| self.binary("*", false, item.clone(), column[j].clone()));
+                let first = terms.next().unwrap()?;
+                terms.try_fold(first, |sum, term| self.binary("+", false, sum, term?))
+            };
+            let mut product = rows.iter()
+                .map(|row| (0..columns[0].len()).map(|j| entry(row, j)).collect::<Result<_>>().map(Value::List))
+                .collect::<Result<Vec<_>>>()?;
+            Ok(match (row, column) {
+                (true, true) => unreachable!(),
+                (true, false) => product.remove(0),
+                (false, true) => Value::List(product.into_iter().map(|row| match row {
+                    Value::List(mut items) => items.remove(0),
+                    _ => unreachable!(),
+                }).collect()),
+                (false, false) => Value::List(product),
+            })
+        }
+
+        fn scalar_binary(&self, operator: &str, percent: bool, op1: Value, op2: Value) -> Result<Value> {
+            if let (Value::Symbolic(_), _) | (_, Value::Symbolic(_)) = (&op1, &op2) {
+                return symbolic_binary(operator, &op1, &op2);
+            }
+            if let Some(result) = temporal_binary(operator, percent, &op1, &op2) {
+                return result;
+            }
+            if let (Value::Complex(_), _) | (_, Value::Complex(_)) = (&op1, &op2) {
+                return complex_binary(operator, percent, op1.as_complex()?, op2.as_complex()?);
+            }
+            if op1.has_units() || op2.has_units() {
+                return quantity_binary(operator, percent, op1.as_quantity()?, op2.as_quantity()?);
+            }
+            if let (Value::Decimal(a), Value::Decimal(b)) = (&op1, &op2) {
+                if let Some(result) = decimal_binary(operator, percent, a, b, self.settings.remainder)? {
+                    return Ok(result);
+                }
+            }
+            if let (Value::Integer(a) | Value::Radix(a, _), Value::Integer(b) | Value::Radix(b, _), Some(modulus)) = (&op1, &op2, &self.settings.modulus) {
+                if let Some(result) = modular_binary(operator, a, b, modulus)? {
+                    return Ok(result);
+                }
+            }
+            if let (Value::Integer(a) | Value::Radix(a, _), Value::Integer(b) | Value::Radix(b, _)) = (&op1, &op2) {
+                if let Some(result) = integer_binary(operator, a, b, self.settings.remainder)? {
+                    return Ok(result);
+                }
+            }
+            real_binary(operator, percent, op1.as_number()?, op2.as_number()?, self.settings).map(Value::Number)
+        }
+
+        fn eval_arithmetic(&self, expr: &Expr) -> Result<f64> {
+            match expr {
+                Expr::Number(number) => Ok(*number),
+
+                Expr::Unary("!", oprand) => Ok(truth(self.eval_number(oprand)? == 0.0)),
+
+                // only the left operand is evaluated if it decides the result,
+                // nonzero values count as true
+                Expr::Binary("&&", op1, op2) => Ok(truth(
+                    self.eval_number(op1)? != 0.0 && self.eval_number(op2)? != 0.0
+                )),
+                Expr::Binary("||", op1, op2) => Ok(truth(
+                    self.eval_number(op1)? != 0.0 || self.eval_number(op2)? != 0.0
+                )),
+
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    impl Scope for Evaluator<'_> {
+        /// Only numbers are constants, since a constant list or quantity
+        /// would compute differently from a number.
+        fn constant(&self, expr: &Expr) -> Option<f64> {
+            match self.eval(expr).ok()? {
+                value @ (Value::Number(_) | Value::Integer(_) | Value::Radix(..) | Value::Decimal(_)) => value.as_number().ok(),
+                _ => None,
+            }
+        }
+
+        fn callee(&self, name: &str, args: &[Expr]) -> Option<&'static Builtin> {
+            if self.is_special(name, args) {
+                return None;
+            }
+            let callee = match self.lookup(name)? {
+                callee if callee.is_function() => callee,
+                _ => self.builtin(name)?,
+            };
+            match callee {
+                Value::Builtin(builtin) => Some(builtin),
+                _ => None,
+            }
+        }
+    }
+
+    /// Shows a quantity, or each in a list, in the `target` unit.
+    fn convert(value: Value, target: &Rc<Target>) -> Result<Value> {
+        if let Value::List(items) = value {
+            return items.into_iter().map(|item| convert(item, target)).collect::<Result<_>>().map(Value::List);
+        }
+        let quantity = value.as_quantity()?;
+        target.convert(quantity)?;
+        Ok(Value::Converted(quantity, target.clone()))
+    }
+
+    /// Rounds a real result to an amount of money with `places` decimals.
+    fn round_money(value: Value, places: u32) -> Value {
+        let decimal = match &value {
+            Value::Decimal(decimal) => Some(decimal.clone()),
+            Value::Integer(integer) => Some(Decimal::from(integer.clone())),
+            Value::Number(number) => Decimal::from_f64(*number),
+            Value::List(items) => return Value::List(items.iter().map(|item| round_money(item.clone(), places)).collect()),
+            _ => None,
+        };
+        decimal.map_or(value, |decimal| Value::Decimal(decimal.round(places)))
+    }
+
+}
+
+pub mod bytecode {
+    use crate::CalcError;
+    use crate::ast::Expr;
+    use crate::eval::{self, Settings};
+    use crate::functions::{Builtin, Call};
+
+    type Result<T> = std::result::Result<T, CalcError>;
+
+    /// binary operators on real numbers, the ones a program can apply
+    const OPERATORS: &[&str] = &["+", "-", "*", "/", "//", "%", "^", "<", "<=", ">", ">=", "==", "!=", "&", "|", "xor", "<<", ">>"];
+
+    /// What the compiler asks about the names of an expression, answered
+    /// by the evaluator.
+    pub trait Scope {
+        /// the value of a part not depending on the parameters, `None` if
+        /// it is not a real number
+        fn constant(&self, expr: &Expr) -> Option<f64>;
+        /// the builtin a call refers to, `None` for calls of other
+        /// functions or builtins taking their arguments unevaluated
+        fn callee(&self, name: &str, args: &[Expr]) -> Option<&'static Builtin>;
+    }
+
+    #[derive(Debug, Clone, Copy)]
+    enum Op {
+        Constant(f64),
+        /// pushes the parameter with this index
+        Load(usize),
+        Negate,
+        /// `!`, 1 for zero and 0 otherwise
+        Not,
+        /// 0 for zero and 1 otherwise, the result of `&&` and `||`
+        Truth,
+        /// the operator, and whether the right operand is a percentage
+        Binary(&'static str, bool),
+        /// calls a builtin with this many arguments from the stack
+        Call(fn(&[f64], &Settings) -> Result<f64>, usize),
+        /// pops a value and goes on at the given instruction if it is zero
+        JumpIfZero(usize),
+        Jump(usize),
+    }
+
+    /// An expression of real numbers compiled for a stack machine, for
+    /// computing it at many values of its parameters without walking the
+    /// tree each time. Parts not depending on the parameters are computed
+    /// once while compiling.
+    #[derive(Debug, Clone)]
+    pub struct Program {
+        code: Vec<Op>,
+        params: usize,
+    }
+
+    struct Compiler<'a, S> {
+        params: &'a [&'a str],
+        scope: &'a S,
+        code: Vec<Op>,
+    }
+
+    impl<S: Scope> Compiler<'_, S> {
+        /// Whether the expression can be computed once, not depending on
+        /// the parameters nor calling anything but builtins.
+        fn is_constant(&self, expr: &Expr) -> bool {
+            match expr {
+                Expr::Number(_) | Expr::Integer(_) | Expr::Decimal(_) => true,
+                Expr::Variable(name) => ! self.params.contains(&name.as_str()),
+                Expr::Unary("+" | "-" | "!", oprand) | Expr::Postfix(_, oprand) => self.is_constant(oprand),
+                Expr::Binary(operator, op1, op2) if OPERATORS.contains(operator) || matches!(*operator, "&&" | "||") => {
+                    self.is_constant(op1) && self.is_constant(op2)
+                },
+                Expr::Conditional(condition, then, otherwise) => {
+                    [condition, then, otherwise].into_iter().all(|expr| self.is_constant(expr))
+                },
+                Expr::Call(name, args) => {
+                    self.scope.callee(name, args).is_some() && args.iter().all(|arg| self.is_constant(arg))
+                },
+                _ => false,
+            }
+        }
+
+        /// Emits a jump to be pointed at its target once that is known.
+        fn jump(&mut self, op: fn(usize) -> Op) -> usize {
+            self.code.push(op(0));
+            self.code.len() - 1
+        }
+
+        /// Points the jump at `at` to the next instruction.
+        fn land(&mut self, at: usize) {
+            let target = self.code.len();
+            self.code[at] = match self.code[at] {
+                Op::JumpIfZero(_) => Op::JumpIfZero(target),
+                _ => Op::Jump(target),
+            };
+        }
+
+        fn compile(&mut self, expr: &Expr) -> Option<()> {
+            if self.is_constant(expr) {
+                let constant = self.scope.constant(expr)?;
+                self.code.push(Op::Constant(constant));
+                return Some(());
+            }
+            match expr {
+                Expr::Variable(name) => {
+                    let index = self.params.iter().position(|param| param == name)?;
+                    self.code.push(Op::Load(index));
+                },
+                Expr::Unary("+", oprand) => self.compile(oprand)?,
+                Expr::Unary(operator @ ("-" | "!"), oprand) => {
+                    self.compile(oprand)?;
+                    self.code.push(if *operator == "-" { Op::Negate } else { Op::Not });
+                },
+                Expr::Postfix("%", oprand) => {
+                    self.compile(oprand)?;
+                    self.code.extend([Op::Constant(100.0), Op::Binary("/", false)]);
+                },
+                // only the left operand is computed if it decides the result
+                Expr::Binary("&&", op1, op2) => {
+                    self.compile(op1)?;
+                    let short = self.jump(Op::JumpIfZero);
+                    self.compile(op2)?;
+                    self.code.push(Op::Truth);
+                    let end = self.jump(Op::Jump);
+                    self.land(short);
+                    self.code.push(Op::Constant(0.0));
+                    self.land(end);
+                },
+                Expr::Binary("||", op1, op2) => {
+                    self.compile(op1)?;
+                    let other = self.jump(Op::JumpIfZero);
+                    self.code.push(Op::Constant(1.0));
+                    let end = self.jump(Op::Jump);
+                    self.land(other);
+                    self.compile(op2)?;
+                    self.code.push(Op::Truth);
+                    self.land(end);
+                },
+                Expr::Binary(operator, op1, op2) if OPERATORS.contains(operator) => {
+                    let percent = matches!(**op2, Expr::Postfix("%", _));
+                    self.compile(op1)?;
+                    self.compile(op2)?;
+                    self.code.push(Op::Binary(operator, percent));
+                },
+                Expr::Conditional(condition, then, otherwise) => {
+                    self.compile(condition)?;
+                    let otherwise_at = self.jump(Op::JumpIfZero);
+                    self.compile(then)?;
+                    let end = self.jump(Op::Jump);
+                    self.land(otherwise_at);
+                    self.compile(otherwise)?;
+                    self.land(end);
+                },
+                Expr::Call(name, args) => {
+                    let builtin = self.scope.callee(name, args)?;
+                    let call = match builtin.call {
+                        Call::Real(call) if builtin.arity.contains(&args.len()) => call,
+                        _ => return None,
+                    };
+                    for arg in args {
+                        self.compile(arg)?;
+                    }
+                    self.code.push(Op::Call(call, args.len()));
+                },
+                _ => return None,
+            }
+            Some(())
+        }
+    }
+
+    impl Program {
+        /// Compiles `expr` as a function of `params`, or gives `None` if
+        /// it computes anything but real numbers, like lists, units or
+        /// calls of user functions.
+        pub fn compile(expr: &Expr, params: &[&str], scope: &impl Scope) -> Option<Program> {
+            let mut compiler = Compiler { params, scope, code: Vec::new() };
+            compiler.compile(expr)?;
+            Some(Program { code: compiler.code, params: params.len() })
+        }
+
+        /// Computes the expression with the parameters set to `args`.
+        pub fn run(&self, args: &[f64], settings: &Settings) -> Result<f64> {
+            assert_eq!(args.len(), self.params, "wrong number of arguments for the program");
+            let mut stack = Vec::new();
+            let mut pc = 0;
+            while let Some(op) = self.code.get(pc) {
+                pc += 1;
+                match *op {
+                    Op::Constant(value) => stack.push(value),
+                    Op::Load(index) => stack.push(args[index]),
+                    Op::Negate => {
+                        let oprand = stack.pop().unwrap();
+                        stack.push(- oprand);
+                    },
+                    Op::Not => {
+                        let oprand = stack.pop().unwrap();
+                        stack.push(if oprand == 0.0 { 1.0 } else { 0.0 });
+                    },
+                    Op::Truth => {
+                        let oprand = stack.pop().unwrap();
+                        stack.push(if oprand != 0.0 { 1.0 } else { 0.0 });
+                    },
+                    Op::Binary(operator, percent) => {
+                        let op2 = stack.pop().unwrap();
+                        let op1 = stack.pop().unwrap();
+                        stack.push(eval::real_binary(operator, percent, op1, op2, settings)?);
+                    },
+                    Op::Call(call, count) => {
+                        let args = stack.split_off(stack.len() - count);
+                        stack.push(call(&args, settings)?);
+                    },
+                    Op::JumpIfZero(target) => {
+                        if stack.pop().unwrap() == 0.0 {
+                            pc = target;
+                        }
+                    },
+                    Op::Jump(target) => pc = target,
+                }
+            }
+            Ok(stack.pop().unwrap())
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::Lexer;
+        use crate::ast::Statement;
+        use crate::eval::{Angle, Context, Evaluator};
+        use crate::parser::Parser;
+        use crate::value::Value;
+
+        const POINTS: [f64; 10] = [-2.5, -1.0, -0.5, 0.0, 0.5, 1.0, 2.0, 3.7, 100.0, f64::INFINITY];
+
+        fn parse(input: &str, settings: &Settings) -> Expr {
+            let mut lexer = Lexer::new(input);
+            match Parser::new(&mut lexer, settings).parse().unwrap().pop() {
+                Some(Statement::Expr(expr)) => expr,
+                statement => panic!("not an expression: {:?}", statement),
+            }
+        }
+
+        /// Checks that the program of `input` computes what the evaluator
+        /// does at every point, failing where it fails; returns whether the
+        /// expression compiled at all.
+        fn agrees(input: &str, settings: &Settings) -> bool {
+            let expr = parse(input, settings);
+            let mut context = Context::new(settings.clone());
+            let program = Program::compile(&expr, &["x"], &Evaluator::new(&context));
+            for x in POINTS {
+                context.variables.insert("x".to_string(), Value::Number(x));
+                let expected = Evaluator::new(&context).eval(&expr).and_then(|value| value.as_number());
+                let Some(program) = &program else { continue };
+                match (program.run(&[x], settings), expected) {
+                    (Ok(y), Ok(expected)) => {
+                        assert!(y == expected || y.is_nan() && expected.is_nan(), "{} at {}: {} but {}", input, x, y, expected);
+                    },
+                    // the evaluator has the error message, or a complex result
+                    // that is not real either
+                    (Err(_), Err(_)) => (),
+                    (y, expected) => panic!("{} at {}: {:?} but {:?}", input, x, y, expected),
+                }
+            }
+            program.is_some()
+        }
+
+        #[test]
+        fn programs_compute_like_the_evaluator() {
+            let inputs = [
+                "x^2 + 3*x - 1", "2^x", "x^-2", "(-x)^3", "x^0.5", "-x^2", "2*pi*x", "1e300*x*x", "x/0",
+                "x % 2", "-x % 3", "10 % x", "x // 3", "-x // 3", "x & 3", "x << 2", "x xor 5",
+                // percentages
+                "x + 10%", "x - 25%", "x * 10%", "x / 50%", "x% + 1",
+                // short circuits, which skip the failing operand
+                "x > 0 && ln(x) > 0.5", "x < 0 || sqrt(x) > 1", "x == 0 || 1 // x", "x && 1 % x",
+                "!x", "!(x - 1)", "x != 0 ? 1 // x : -1", "x > 1 ? ln(x) : -x", "1 < x < 3",
+                // builtins, failing outside their domains
+                "sin(x) + cos(2*x)", "atan2(x, 1)", "max(x, 1, 2)", "min(x)", "floor(x) + ceil(x)", "round(x, 1)",
+                "sqrt(x)", "ln(x)", "acos(x)", "asin(x/2)", "gamma(x)", "gcd(x, 12)", "fact(3)*x", "mod(x, 0)",
+            ];
+            for input in inputs {
+                assert!(agrees(input, &Settings::default()), "{} did not compile", input);
+            }
+            let degrees = Settings { angle: Angle::Degrees, ..Settings::default() };
+            assert!(agrees("sin(x) + acos(x/4)", &degrees));
+        }
+
+        /// Where a program fails, like `sqrt` of a negative number, the
+        /// point is evaluated again, which gives the error of the evaluator.
+        #[test]
+        fn failing_points_fall_back_to_the_evaluator() {
+            let eval = |input| crate::eval_str(input).unwrap_err();
+            assert!(Program::compile(&parse("sqrt(x)", &Settings::default()), &["x"], &Evaluator::new(&Context::default()))
+                .unwrap().run(&[-1.0], &Settings::default()).is_err());
+            assert_eq!(eval("integrate(sqrt(x), x, -1, 1)"), CalcError::TypeMismatch("expected a real number, found a complex number"));
+            assert_eq!(eval("integrate(1 // x, x, -1, 1)"), CalcError::DivisionByZero);
+            assert_eq!(eval("nderiv(acos(x), x, 2)"), CalcError::Domain("argument out of the domain of the inverse function"));
+            // and where the failing operand is skipped there is no error
+            assert!(crate::eval_str("integrate(x > 0 ? 1 // (x + 1) : 0, x, -1, 1)").is_ok());
+        }
+
+        #[test]
+        fn anything_but_real_numbers_is_left_to_the_evaluator() {
+            for input in ["[x, 1]", "x m", "f(x)", "x!", "8 // 0 + x", "sum([x, 1])"] {
+                assert!(! agrees(input, &Settings::default()), "{} compiled", input);
+            }
+        }
+    }
+}
+
+pub mod stream {
+    use std::collections::VecDeque;
+    use std::fmt;
+    use std::io::{self, BufRead};
+
+    use crate::{CalcError, Diagnostic, Lexer};
+    use crate::eval::Context;
+    use crate::parser::Parser;
+    use crate::value::Value;
+
+    /// Why a statement of a stream gave no result.
+    #[derive(Debug)]
+    pub enum StreamError {
+        Read(io::Error),
+        /// a syntax error in the line with the given number, counted from 1
+        Syntax(usize, Diagnostic),
+        Eval(CalcError),
+    }
+
+    impl fmt::Display for StreamError {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self {
+                StreamError::Read(error) => write!(f, "{}", error),
+                StreamError::Syntax(line, diagnostic) => write!(f, "line {}: {}", line, diagnostic),
+                StreamError::Eval(error) => write!(f, "{}", error),
+            }
+        }
+    }
+
+    impl std::error::Error for StreamError {}
+
+    /// The values of the statements read from a `BufRead`, one line after
+    /// another, each line holding statements separated by `;`. Variables
+    /// persist from line to line and `ans` is the last value, like in the
+    /// REPL; an error ends the statements of its line.
+    pub struct Results<R> {
+        lines: io::Lines<R>,
+        line: usize,
+        context: Context,
+        /// the results of the current line not yet taken
+        pending: VecDeque<Result<Value, StreamError>>,
+    }
+
+    impl<R: BufRead> Results<R> {
+        pub fn new(reader: R, context: Context) -> Self {
+            Results {
+                lines: reader.lines(),
+                line: 0,
+                context,
+                pending: VecDeque::new(),
+            }
+        }
+
+        fn run(&mut self, input: &str) {
+            let mut lexer = Lexer::with_operators(input, &self.context.settings.operators);
+            let statements = match Parser::new(&mut lexer, &self.context.settings).parse() {
+                Ok(statements) => statements,
+                Err(diagnostics) => {
+                    let line = self.line;
+                    self.pending.extend(diagnostics.into_iter().map(|diagnostic| Err(StreamError::Syntax(line, diagnostic))));
+                    return;
+                },
+            };
+            for statement in &statements {
+                match self.context.exec(statement) {
+                    Ok(Some(value)) => {
+                        self.context.variables.insert("ans".to_string(), value.clone());
+                        self.pending.push_back(Ok(value));
+                    },
+                    Ok(None) => (),
+                    Err(error) => {
+                        self.pending.push_back(Err(StreamError::Eval(error)));
+                        return;
+                    },
+                }
+            }
+        }
+    }
+
+    impl<R: BufRead> Iterator for Results<R> {
+        type Item = Result<Value, StreamError>;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            while self.pending.is_empty() {
+                let input = match self.lines.next()? {
+                    Ok(input) => input,
+                    Err(error) => return Some(Err(StreamError::Read(error))),
+                };
+                self.line += 1;
+                self.run(&input);
+            }
+            self.pending.pop_front()
+        }
+    }
+}
+
+/// Evaluates the lines read from `reader`, like a file or standard input,
+/// giving the value of each statement in turn.
+pub fn eval_reader<R: io::BufRead>(reader: R, context: Context) -> stream::Results<R> {
+    stream::Results::new(reader, context)
+}
+
+/// Evaluates a line with the default settings and no variables defined
+/// beforehand, giving the value of its last statement.
+pub fn eval_str(input: &str) -> Result<f64, CalcError> {
+    eval_str_with(input, &Settings::default())
+}
+
+/// Evaluates a line like `eval_str`, with the given settings and the
+/// operators registered in them.
+pub fn eval_str_with(input: &str, settings: &Settings) -> Result<f64, CalcError> {
+    eval_str_in(input, &mut Context::new(settings.clone()))
+}
+
+/// Evaluates a line in a context, which keeps the variables and functions
+/// it defines for the next line.
+pub fn eval_str_in(input: &str, context: &mut Context) -> Result<f64, CalcError> {
+    let mut lexer = Lexer::with_operators(input, &context.settings.operators);
+    let statements = Parser::new(&mut lexer, &context.settings).parse().map_err(|diagnostics| diagnostics[0].error)?;
+    let mut result = None;
+    for statement in &statements {
+        if let Some(value) = context.exec(statement)? {
+            result = Some(value);
+        }
+    }
+    result.ok_or(CalcError::NothingToEvaluate)?.as_number()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use value::Value;
+
+    /// The value of the last statement of `input`, with the default
+    /// settings.
+    fn eval(input: &str) -> Result<Value, CalcError> {
+        eval_with(input, &Settings::default())
+    }
+
+    fn eval_with(input: &str, settings: &Settings) -> Result<Value, CalcError> {
+        let mut context = Context::new(settings.clone());
+        let mut lexer = Lexer::with_operators(input, &context.settings.operators);
+        let statements = Parser::new(&mut lexer, &context.settings).parse().map_err(|diagnostics| diagnostics[0].error)?;
+        let mut result = None;
+        for statement in &statements {
+            result = context.exec(statement)?.or(result);
+        }
+        result.ok_or(CalcError::NothingToEvaluate)
+    }
+
+    fn show(input: &str) -> String {
+        eval(input).unwrap().to_string()
+    }
+
+    #[test]
+    fn indexed_sums_take_a_variable_first_of_four_arguments() {
+        assert_eq!(show("sum(k, 1, 10, 5)"), "50");
+        assert_eq!(show("k = 100; sum(k, 1, 10, 5)"), "50");
+        assert_eq!(show("k = 100; prod(k, 1, 4, k)"), "24");
+        assert_eq!(show("sum(k, 1, 100, k^2)"), "338350");
+        // other arguments are summed as they are
+        assert_eq!(show("k = 100; sum(k, 1, 10)"), "111");
+        assert_eq!(show("sum(1, 2, 3, 4)"), "10");
+        assert_eq!(show("k = 100; sum([k, 1, 10, 5])"), "116");
+    }
+
+    #[test]
+    fn rounding_to_extreme_digits_stays_finite() {
+        assert_eq!(show("round(1.5, 1e10)"), "1.5");
+        assert_eq!(show("round(1e300 + 0.5, 400)"), show("1e300 + 0.5"));
+        assert_eq!(show("round(1.5, -400)"), "0");
+        assert_eq!(show("round(3.14159, 2)"), "3.14");
+        assert_eq!(show("round(1250.5, -2)"), "1300");
+        assert!(matches!(eval("round(1, 0.5)"), Err(CalcError::InvalidArguments(_))));
+        assert!(matches!(eval("round(1, inf)"), Err(CalcError::InvalidArguments(_))));
+    }
+
+    #[test]
+    fn integrals_are_finite_or_fail() {
+        assert_eq!(show("integrate(x, x, 0, 1)"), "0.5");
+        assert_eq!(eval("integrate(1/x, x, 0, 1)").unwrap_err(), CalcError::Domain("integrand is not finite on the interval"));
+        assert_eq!(eval("integrate(x, x, 0, inf)").unwrap_err(), CalcError::InvalidArguments("bounds must be finite"));
+        assert_eq!(eval("integrate(x, x, nan, 1)").unwrap_err(), CalcError::InvalidArguments("bounds must be finite"));
+        assert_eq!(eval("integrate(1e308, x, -1e308, 1e308)").unwrap_err(), CalcError::Overflow("integral is not finite"));
+    }
+
+    #[test]
+    fn operators_cannot_take_builtin_names() {
+        use operators::Associativity::Left;
+
+        let mut settings = eval::Settings::default();
+        let taken = CalcError::InvalidDefinition("the symbol names a builtin function or constant");
+        assert_eq!(settings.operators.add_binary("sqrt", 110, Left, |a, _| Ok(a)).unwrap_err(), taken);
+        assert_eq!(settings.operators.add_unary("pi", 130, Ok).unwrap_err(), taken);
+        assert_eq!(settings.operators.add_binary("mod", 110, Left, |a, _| Ok(a)).unwrap_err(), taken);
+        assert_eq!(settings.operators.add_binary("xor", 110, Left, |a, _| Ok(a)).unwrap_err(),
+            CalcError::InvalidDefinition("the operator is already defined"));
+        assert!(settings.operators.add_binary("div", 110, Left, |a, _| Ok(a)).is_ok());
+    }
+
+    #[test]
+    fn brackets_after_a_call_call_its_result() {
+        assert_eq!(show("f(x) = y -> x * y; f(3)(4)"), "12");
+        assert_eq!(show("(x -> x + 1)(1)"), "2");
+        assert_eq!(show("((a, b) -> a - b)(5, 3)"), "2");
+        assert_eq!(show("g(x) = y -> z -> x + y + z; g(1)(2)(3)"), "6");
+        assert_eq!(show("f(x) = y -> x * y; |f(-3)(4)|"), "12");
+        assert_eq!(eval("sin(0)(2)").unwrap_err(), CalcError::NotAFunction);
+        // numbers and variables are still multiplied
+        assert_eq!(show("2(3)"), "6");
+        assert_eq!(show("(1 + 2)(3 + 4)"), "21");
+
+        let mut lexer = Lexer::new("(x -> x + 1)(2)");
+        let statements = Parser::new(&mut lexer, &eval::Settings::default()).parse().unwrap();
+        let [ast::Statement::Expr(expr)] = statements.as_slice() else { panic!("not an expression") };
+        assert_eq!(expr.to_string(), "((x) -> x + 1)(2)");
+    }
+
+    #[test]
+    fn symbolic_expressions_compare_by_their_simplified_form() {
+        let symbolic = |input: &str| {
+            let mut context = Context::new(eval::Settings { symbolic: true, ..eval::Settings::default() });
+            let mut lexer = Lexer::new(input);
+            let statements = Parser::new(&mut lexer, &context.settings).parse().unwrap();
+            context.exec(&statements[0]).map(|value| value.unwrap().to_string())
+        };
+        assert_eq!(symbolic("2*x == 2*x").unwrap(), "1");
+        assert_eq!(symbolic("x + x == 2*x").unwrap(), "1");
+        assert_eq!(symbolic("(x + 1)*2 != 2*x + 2").unwrap(), "0");
+        assert_eq!(symbolic("x == 1").unwrap(), "0");
+        assert_eq!(symbolic("x != y").unwrap(), "1");
+        assert_eq!(symbolic("x < 1").unwrap_err(), CalcError::TypeMismatch("comparisons need numeric values"));
+        assert_eq!(symbolic("x >= y").unwrap_err(), CalcError::TypeMismatch("comparisons need numeric values"));
+    }
+
+    #[test]
+    fn remembered_calls_follow_what_they_read() {
+        let mut context = Context::default();
+        let run = |context: &mut Context, input: &str| {
+            let mut lexer = Lexer::new(input);
+            let statements = Parser::new(&mut lexer, &context.settings).parse().unwrap();
+            let mut result = None;
+            for statement in &statements {
+                result = context.exec(statement).unwrap().or(result);
+            }
+            result.map(|value| value.to_string())
+        };
+        assert_eq!(run(&mut context, "fib(n) = n < 2 ? n : fib(n - 1) + fib(n - 2); fib(40)").unwrap(), "102334155");
+        assert_eq!(run(&mut context, "a = 1; f(x) = x + a; f(1)").unwrap(), "2");
+        assert_eq!(run(&mut context, "a = 5; f(1)").unwrap(), "6");
+        // variables set by the embedder count as well
+        context.variables.insert("a".to_string(), Value::Integer(BigInt::from(10)));
+        assert_eq!(run(&mut context, "f(1)").unwrap(), "11");
+        // and so do the variables of the functions a function calls
+        context.variables.insert("b".to_string(), Value::Integer(BigInt::from(2)));
+        assert_eq!(run(&mut context, "g(x) = x + b; h(x) = g(x) * 2; h(1)").unwrap(), "6");
+        context.variables.insert("b".to_string(), Value::Integer(BigInt::from(3)));
+        assert_eq!(run(&mut context, "h(1)").unwrap(), "8");
+        assert_eq!(run(&mut context, "g(x) = x; h(1)").unwrap(), "2");
+        assert_eq!(run(&mut context, "k(x) = sin(x); k(90) == 1").unwrap(), "0");
+        context.settings.angle = eval::Angle::Degrees;
+        assert_eq!(run(&mut context, "k(90) == 1").unwrap(), "1");
+        assert_eq!(run(&mut context, "r(x) = rand(); r(1) != r(1)").unwrap(), "1");
+    }
+
+    #[test]
+    fn bitwise_operators_keep_all_bits_of_integers() {
+        assert_eq!(show("(2^60 + 1) & 1"), "1");
+        assert_eq!(show("(2^60 + 1) xor 2^60"), "1");
+        assert_eq!(show("0x7FFFFFFFFFFFFFFF & 1"), "1");
+        assert_eq!(show("0x7FFFFFFFFFFFFFFF | 0"), "9223372036854775807");
+        assert_eq!(show("1 << 63"), "-9223372036854775808");
+        assert_eq!(show("-1 >> 1"), "-1");
+        assert_eq!(eval("2^63 & 1").unwrap_err(), CalcError::Overflow("integer out of range"));
+        assert_eq!(eval("1 << 64").unwrap_err(), CalcError::InvalidArguments("shift amount out of range"));
+        assert_eq!(eval("5.5 & 1").unwrap_err(), CalcError::TypeMismatch("operand is not an integer"));
+    }
+
+    #[test]
+    fn bit_functions_take_all_64_bits() {
+        assert_eq!(show("popcount(0xFFFFFFFFFFFFFFFF)"), "64");
+        assert_eq!(show("popcount(-1)"), "64");
+        assert_eq!(show("popcount([1, 3])"), "[1, 2]");
+        assert_eq!(show("clz(0x8000000000000000)"), "0");
+        assert_eq!(show("ctz(0x8000000000000000)"), "63");
+        assert_eq!(show("clz(0)"), "64");
+        assert_eq!(show("rotl(1, 63)"), "9223372036854775808");
+        assert_eq!(show("rotr(1, 1)"), "9223372036854775808");
+        assert_eq!(show("rotl(0x8000000000000001, 1)"), "3");
+        assert_eq!(show("rotl(-1, 1)"), "18446744073709551615");
+        assert_eq!(show("rotl(0x80, 1, 8)"), "1");
+        assert_eq!(show("rotr(1, 1, 8)"), "128");
+        assert_eq!(eval("rotl(0x100, 1, 8)").unwrap_err(), CalcError::Domain("operand does not fit in the width"));
+        assert_eq!(eval("popcount(2^64)").unwrap_err(), CalcError::Overflow("integer out of range"));
+        assert_eq!(eval("popcount(1.5)").unwrap_err(), CalcError::TypeMismatch("operand is not an integer"));
+    }
+
+    #[test]
+    fn decimal_mode_adds_tenths_exactly() {
+        let decimal = Settings { decimal: true, ..Settings::default() };
+        assert_eq!(eval_with("0.1 + 0.2", &decimal).unwrap().to_string(), "0.3");
+        assert_eq!(eval_with("0.1 + 0.2 == 0.3", &decimal).unwrap().to_string(), "1");
+        assert_eq!(show("0.1 + 0.2 == 0.3"), "0");
+        assert_eq!(eval_with("10 / 4", &decimal).unwrap().to_string(), "2.5");
+        assert_eq!(eval_with("1 / 3", &decimal).unwrap().to_string(), "0.33333333333333333333");
+        // roots are left to floating point
+        assert_eq!(eval_with("2^0.5", &decimal).unwrap().to_string(), "1.4142135623730951");
+
+        let money = Settings { decimal: true, money: Some(2), ..Settings::default() };
+        assert_eq!(eval_with("10 / 3", &money).unwrap().to_string(), "3.33");
+        assert_eq!(eval_with("0.125 + 0", &money).unwrap().to_string(), "0.13");
+    }
+
+    #[test]
+    fn quantities_keep_their_dimensions() {
+        assert_eq!(show("3 m / 2 s"), "1.5 m/s");
+        assert_eq!(show("2 km + 300 m"), "2300 m");
+        assert_eq!(show("(3 m)^2"), "9 m^2");
+        assert_eq!(eval("3 m + 2 s").unwrap_err(), CalcError::TypeMismatch("incompatible units"));
+        assert_eq!(eval("3 m < 2 kg").unwrap_err(), CalcError::TypeMismatch("incompatible units"));
+    }
+
+    #[test]
+    fn quantities_convert_to_units_of_their_dimension() {
+        assert_eq!(show("5 km to miles"), "3.10685596119 miles");
+        assert_eq!(show("5 km in miles"), "3.10685596119 miles");
+        assert_eq!(show("72 F to C"), "22.2222222222 C");
+        assert_eq!(show("1 GiB to MB"), "1073.741824 MB");
+        assert_eq!(show("100 km/h to m/s"), "27.7777777778 m/s");
+        assert_eq!(eval("3 kg to m").unwrap_err(), CalcError::TypeMismatch("incompatible units"));
+    }
+
+    #[test]
+    fn dates_and_times_move_by_durations() {
+        assert_eq!(show("2024-03-01 + 45 days"), "2024-04-15");
+        assert_eq!(show("2024-02-28 + 1 day"), "2024-02-29");
+        assert_eq!(show("2024-03-01 - 2024-01-01"), "60 days");
+        assert_eq!(show("18:30 - 7:45"), "10:45");
+        assert_eq!(show("(18:30 - 7:45) to h"), "10.75 h");
+        assert_eq!(eval("2024-13-01").unwrap_err(), CalcError::InvalidLiteral("no such date"));
+        assert_eq!(eval("2024-03-01 + 2024-03-01").unwrap_err(), CalcError::TypeMismatch("operator is not defined for dates"));
+    }
+
+    #[test]
+    fn matrices_multiply_as_matrices() {
+        assert_eq!(show("[[1, 2], [3, 4]] * [[5, 6], [7, 8]]"), "[[19, 22], [43, 50]]");
+        assert_eq!(show("[[1, 2], [3, 4]] + [[1, 1], [1, 1]]"), "[[2, 3], [4, 5]]");
+        assert_eq!(show("[[1, 2], [3, 4]] * 2"), "[[2, 4], [6, 8]]");
+        assert_eq!(show("transpose([[1, 2, 3]])"), "[[1], [2], [3]]");
+        assert_eq!(eval("[[1, 2]] * [[1, 2]]").unwrap_err(), CalcError::TypeMismatch("matrix shapes do not match for a product"));
+        assert_eq!(eval("[[1, 2], [3, 4]] + [[1, 1]]").unwrap_err(), CalcError::TypeMismatch("lists differ in length"));
+    }
+
+    #[test]
+    fn unknown_variables_stay_symbols_in_symbolic_mode() {
+        let symbolic = Settings { symbolic: true, ..Settings::default() };
+        assert_eq!(eval_with("2*x + 3*x", &symbolic).unwrap().to_string(), "5*x");
+        assert_eq!(eval_with("(x + 1)*(x + 1)", &symbolic).unwrap().to_string(), "x^2 + 2*x + 1");
+        assert_eq!(eval_with("x - x", &symbolic).unwrap().to_string(), "0");
+        assert_eq!(eval_with("sin(x) + sin(x)", &symbolic).unwrap().to_string(), "2*sin(x)");
+        assert_eq!(eval_with("f(y) = y + y; f(x)", &symbolic).unwrap().to_string(), "2*x");
+        assert_eq!(eval_with("x = 3; 2*x + y", &symbolic).unwrap().to_string(), "y + 6");
+        assert_eq!(eval("2*x + 3*x").unwrap_err(), CalcError::UnknownIdentifier);
+    }
+
+    #[test]
+    fn derivatives_follow_the_rules() {
+        assert_eq!(show("diff(x^2 + sin(x), x)"), "2*x + cos(x)");
+        assert_eq!(show("diff(x^3, x)"), "3*x^2");
+        assert_eq!(show("diff(ln(x), x)"), "1/x");
+        assert_eq!(show("diff(5, x)"), "0");
+        assert_eq!(eval("diff(x, 1)").unwrap_err(), CalcError::InvalidArguments("expected a variable to differentiate by"));
+        assert_eq!(eval("diff(foo(x), x)").unwrap_err(), CalcError::UnknownFunction);
+    }
+
+    #[test]
+    fn simplify_gathers_like_terms() {
+        assert_eq!(show("simplify(x*1 + 0 + 2*x)"), "3*x");
+        assert_eq!(show("simplify(2 * 3 + x - x)"), "6");
+        assert_eq!(show("simplify(x * y - y * x)"), "0");
+        assert_eq!(show("a = 2; simplify(a * x)"), "2*x");
+    }
+
+    #[test]
+    fn equations_are_solved_for_their_variable() {
+        assert_eq!(show("solve(2*x + 3 = 11, x)"), "4");
+        assert_eq!(show("solve(2*x + 3, x)"), "-1.5");
+        assert_eq!(show("solve(x^2 = 4, x)"), "[-2, 2]");
+        assert_eq!(show("solve(cos(x) = x, x)"), "0.739085133215");
+        assert_eq!(eval("solve(x^2 = -1, x)").unwrap_err(), CalcError::NoSolution("no real solution found"));
+    }
+
+    #[test]
+    fn polynomials_have_all_their_roots() {
+        assert_eq!(show("roots([1, 0, -4])"), "[-2, 2]");
+        assert_eq!(show("roots(x^2 - 4, x)"), "[-2, 2]");
+        assert_eq!(show("roots([1, -6, 11, -6])"), "[1, 2, 3]");
+        assert_eq!(show("roots([1, 0, 1])"), "[-1i, 1i]");
+        assert_eq!(eval("roots(1)").unwrap_err(), CalcError::InvalidArguments("expected a list of coefficients"));
+    }
+
+    #[test]
+    fn lines_evaluate_through_the_library() {
+        assert_eq!(eval_str("1 + 2 * 3"), Ok(7.0));
+        assert_eq!(eval_str("x = 4; x^2"), Ok(16.0));
+        assert_eq!(eval_str("x"), Err(CalcError::UnknownIdentifier));
+        assert_eq!(eval_str(""), Err(CalcError::NothingToEvaluate));
+        assert_eq!(eval_str("1 + * 2"), Err(CalcError::UnexpectedToken("invalid operator")));
+        assert_eq!(eval_str("[1, 2]"), Err(CalcError::TypeMismatch("expected a number, found a list")));
+    }
+
+    #[test]
+    fn registered_operators_bind_by_their_precedence() {
+        use operators::Associativity::{Left, Right};
+
+        let number = |value: Value| value.as_number();
+        let mut settings = Settings::default();
+        settings.operators.add_binary("minus", 100, Left, move |a, b| Ok(Value::Number(number(a)? - number(b)?))).unwrap();
+        settings.operators.add_binary("rminus", 100, Right, move |a, b| Ok(Value::Number(number(a)? - number(b)?))).unwrap();
+        settings.operators.add_binary("over", 110, Left, move |a, b| Ok(Value::Number(number(a)? / number(b)?))).unwrap();
+        settings.operators.add_binary("loose", 5, Left, move |a, b| Ok(Value::Number(number(a)? / number(b)?))).unwrap();
+        settings.operators.add_unary("twice", 130, move |a| Ok(Value::Number(2.0 * number(a)?))).unwrap();
+        settings.operators.add_unary("half", 100, move |a| Ok(Value::Number(number(a)? / 2.0))).unwrap();
+        assert_eq!(eval_str_with("10 minus 3 minus 2", &settings), Ok(5.0));
+        assert_eq!(eval_str_with("10 rminus 3 rminus 2", &settings), Ok(9.0));
+        assert_eq!(eval_str_with("1 + 6 over 2", &settings), Ok(4.0));
+        assert_eq!(eval_str_with("1 + 6 loose 2 + 1", &settings), Ok(7.0 / 3.0));
+        // a prefix operator takes in the operators binding at least as tight
+        assert_eq!(eval_str_with("twice 3^2", &settings), Ok(36.0));
+        assert_eq!(eval_str_with("half 3 * 2 + 1", &settings), Ok(3.5));
+
+        let taken = CalcError::InvalidDefinition("the operator is already defined");
+        assert_eq!(settings.operators.add_binary("over", 90, Left, |a, _| Ok(a)).unwrap_err(), taken);
+        assert_eq!(settings.operators.add_binary("+", 90, Left, |a, _| Ok(a)).unwrap_err(), taken);
+        assert_eq!(settings.operators.add_unary("a b", 90, Ok).unwrap_err(), CalcError::InvalidDefinition("invalid operator symbol"));
+        assert_eq!(settings.operators.add_unary("**", 90, Ok).unwrap_err(), taken);
+        // without them the words are unknown
+        assert!(eval_str("10 minus 3").is_err());
+    }
+
+    #[test]
+    fn plugin_functions_are_called_like_builtins() {
+        struct Twice;
+
+        impl plugins::Function for Twice {
+            fn name(&self) -> &str {
+                "twice"
+            }
+
+            fn arity(&self) -> std::ops::RangeInclusive<usize> {
+                1..=1
+            }
+
+            fn call(&self, args: &[Value]) -> Result<Value, CalcError> {
+                Ok(Value::Number(2.0 * args[0].as_number()?))
+            }
+        }
+
+        let mut context = Context::default();
+        context.functions.add(Twice).unwrap();
+        assert_eq!(eval_str_in("twice(4)", &mut context), Ok(8.0));
+        assert_eq!(eval_str_in("sum(map(twice, [1, 2, 3]))", &mut context), Ok(12.0));
+        assert_eq!(eval_str_in("twice(1, 2)", &mut context), Err(CalcError::TooManyArguments));
+        assert_eq!(eval_str_in("twice(x) = x", &mut context), Err(CalcError::InvalidDefinition("cannot redefine a builtin function")));
+        assert_eq!(context.functions.add(Twice).unwrap_err(), CalcError::InvalidDefinition("the function is already defined"));
+        assert!(eval_str("twice(4)").is_err());
+    }
+
+    #[test]
+    fn syntax_errors_point_at_their_place() {
+        let diagnostics = |input: &str| {
+            let mut lexer = Lexer::new(input);
+            Parser::new(&mut lexer, &Settings::default()).parse().unwrap_err()
+        };
+        let [diagnostic] = diagnostics("1 + * 2")[..] else { panic!("not one error") };
+        assert_eq!(diagnostic.span, Span { start: 4, end: 5 });
+        assert_eq!(diagnostic.render("1 + * 2"), "1 + * 2\n    ^ invalid operator");
+        let [diagnostic] = diagnostics("sqrt(2")[..] else { panic!("not one error") };
+        assert_eq!(diagnostic.error, CalcError::UnmatchedParen);
+        let [diagnostic] = diagnostics("2024-13-01 + 1")[..] else { panic!("not one error") };
+        assert_eq!(diagnostic.render("2024-13-01 + 1"), "2024-13-01 + 1\n^^^^^^^^^^ no such date");
+    }
+
+    #[test]
+    fn parsing_goes_on_after_an_error() {
+        let input = "1 + * 2; 3 +; 4 )";
+        let mut lexer = Lexer::new(input);
+        let diagnostics = Parser::new(&mut lexer, &Settings::default()).parse().unwrap_err();
+        let places: Vec<_> = diagnostics.iter().map(|diagnostic| (diagnostic.span.start, diagnostic.error)).collect();
+        assert_eq!(places, [
+            (4, CalcError::UnexpectedToken("invalid operator")),
+            (12, CalcError::UnexpectedToken("invalid operator")),
+            (16, CalcError::UnexpectedToken("invalid expression")),
+        ]);
+        // the statements between the errors are not evaluated
+        assert_eq!(eval_str("x = 1; 1 + * 2; x"), Err(CalcError::UnexpectedToken("invalid operator")));
+    }
+
+    #[test]
+    fn streams_give_every_result_and_go_on_after_errors() {
+        use stream::StreamError;
+
+        let input = "1 + 2\nans * 2; x = 5; x + 1\n1 + * 2\n1 / 0; 7\nx * 8\n";
+        let results: Vec<_> = eval_reader(input.as_bytes(), Context::default()).map(|result| match result {
+            Ok(value) => value.to_string(),
+            Err(StreamError::Syntax(line, diagnostic)) => format!("line {}: {} at {}", line, diagnostic, diagnostic.span.start),
+            Err(error) => error.to_string(),
+        }).collect();
+        assert_eq!(results, ["3", "6", "6", "line 3: invalid operator at 4", "division by zero", "40"]);
+        assert_eq!(eval_reader("".as_bytes(), Context::default()).count(), 0);
+    }
+
+    #[test]
+    fn every_kind_of_number_shares_the_arithmetic() {
+        use std::cmp::Ordering;
+        use complex::Complex;
+        use decimal::Decimal;
+        use numeric::Numeric;
+
+        let tenth = <Decimal as Numeric>::parse("0.1").unwrap();
+        assert_eq!(tenth.add(&tenth).add(&tenth), <Decimal as Numeric>::parse("0.3").unwrap());
+        let (seven, two) = (BigInt::from(7), BigInt::from(2));
+        assert_eq!(seven.div(&two), Ok(None));
+        assert_eq!(seven.mul(&two).div(&two), Ok(Some(seven.clone())));
+        assert_eq!(seven.compare(&two), Some(Ordering::Greater));
+        assert_eq!(<BigInt as Numeric>::parse("1.5"), None);
+        assert_eq!(Numeric::neg(&2.5f64), -2.5);
+        let i = Complex::new(0.0, 1.0);
+        assert_eq!(i.mul(&i), Complex::new(-1.0, 0.0));
+        assert_eq!(i.compare(&i), None);
+        assert_eq!(show("(1 + 2i) * (1 - 2i)"), "5");
+        assert_eq!(eval("1i < 2i").unwrap_err(), CalcError::TypeMismatch("operator is not defined for complex numbers"));
+    }
+
+    #[test]
+    fn contexts_share_nothing() {
+        let mut first = Context::default();
+        let mut second = Context::new(Settings { angle: eval::Angle::Degrees, ..Settings::default() });
+        assert_eq!(eval_str_in("x = 2; f(y) = y * x", &mut first), Err(CalcError::NothingToEvaluate));
+        assert_eq!(eval_str_in("f(x) * 3", &mut first), Ok(12.0));
+        assert_eq!(eval_str_in("x", &mut second), Err(CalcError::UnknownIdentifier));
+        assert_eq!(eval_str_in("f(1)", &mut second), Err(CalcError::UnknownFunction));
+        assert_eq!(eval_str_in("sin(90)", &mut second), Ok(1.0));
+        assert_eq!(eval_str_in("sin(0)", &mut first), Ok(0.0));
+        assert!(first.variables.contains_key("f") && second.variables.is_empty());
+    }
+
+    #[test]
+    fn constants_fold_before_evaluation() {
+        let fold = |input: &str, settings: &Settings| {
+            let mut lexer = Lexer::new(input);
+            let statements = Parser::new(&mut lexer, settings).parse().unwrap();
+            let [ast::Statement::Expr(expr)] = statements.as_slice() else { panic!("not an expression") };
+            expr.fold_constants(settings).to_string()
+        };
+        let settings = Settings::default();
+        assert_eq!(fold("2*pi*r", &settings), "6.283185307179586*r");
+        assert_eq!(fold("r * (1 + 2)", &settings), "r*3");
+        // calls, percentages and failing parts are left to the evaluation
+        assert_eq!(fold("sqrt(4) * r", &settings), "sqrt(4)*r");
+        assert_eq!(fold("r + 10%", &settings), "r + 10%");
+        assert_eq!(fold("r + 1 // 0", &settings), "r + 1 // 0");
+        assert_eq!(fold("(x -> x * (2 + 3))(r)", &settings), "((x) -> x*5)(r)");
+        let decimal = Settings { decimal: true, ..Settings::default() };
+        assert_eq!(fold("r * (0.1 + 0.2)", &decimal), "r*0.3");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn syntax_trees_serialize() {
+        use ast::Statement;
+        use eval::Settings;
+
+        let mut settings = Settings::default();
+        settings.operators.add_binary("div", 110, operators::Associativity::Left, |a, _| Ok(a)).unwrap();
+        let inputs = "x = -2.5 + 3 * 4 // 5 % 6; f(x) = x < 1 ? 10% : x!; g = (a, b) -> a div b; g(7, 2); \
+            3 km/h to m/s; 2024-03-01 + 18:30; let y = 2^100 in y; [1, 2][0:1]";
+        let mut lexer = Lexer::with_operators(inputs, &settings.operators);
+        let statements = Parser::new(&mut lexer, &settings).parse().unwrap();
+        let json = serde_json::to_string(&statements).unwrap();
+        assert_eq!(serde_json::from_str::<Vec<Statement>>(&json).unwrap(), statements);
+
+        let settings = Settings { decimal: true, ..Settings::default() };
+        let mut lexer = Lexer::new("0.1 + 123456789012345678901234567890");
+        let statements = Parser::new(&mut lexer, &settings).parse().unwrap();
+        let json = serde_json::to_string(&statements).unwrap();
+        assert_eq!(serde_json::from_str::<Vec<Statement>>(&json).unwrap(), statements);
+        assert!(serde_json::from_str::<Vec<Statement>>(r#"[{"Expr":{"Integer":"12x"}}]"#).is_err());
+    }
+
+    #[test]
+    fn counting_functions_are_exact_for_integers() {
+        assert_eq!(show("fact(25)"), "15511210043330985984000000");
+        assert_eq!(show("ncr(60, 30)"), "118264581564861424");
+        assert_eq!(show("npr(30, 20)"), "73096577329197271449600000");
+        assert_eq!(show("fact(200)"), show("200!"));
+        assert_eq!(show("ncr(5, 7)"), "0");
+        // beyond the factors multiplied out exactly it is floating point
+        assert_eq!(eval("fact(6000)").unwrap_err(), CalcError::Overflow("result overflows"));
+        assert_eq!(eval("fact(-1)").unwrap_err(), CalcError::Domain("argument is negative"));
+    }
+
+    #[test]
+    fn integer_functions_are_exact_for_integers() {
+        assert_eq!(show("gcd(2^60 + 1, 2^60 + 3)"), "1");
+        assert_eq!(show("gcd(2^60 * 6, 2^60 * 9, -2^60 * 15)"), show("2^60 * 3"));
+        assert_eq!(show("lcm(2^40 + 1, 2^40 + 3)"), show("(2^40 + 1) * (2^40 + 3)"));
+        assert_eq!(show("lcm(4, 0)"), "0");
+        assert_eq!(show("mod(2^60 + 1, 7)"), "2");
+        assert_eq!(show("mod(-(2^60 + 1), 7)"), "5");
+        assert_eq!(show("rem(-(2^60 + 1), 7)"), "-2");
+        assert_eq!(show("abs(-(2^60 + 1))"), show("2^60 + 1"));
+        assert_eq!(show("min(2^60 + 1, 2^60 + 3)"), show("2^60 + 1"));
+        assert_eq!(show("max([2^60 + 1, 2^60 + 3])"), show("2^60 + 3"));
+        assert_eq!(show("sum(2^60, 1)"), "1152921504606846977");
+        assert_eq!(show("prod([2^40 + 1, 3])"), "3298534883331");
+        for function in ["floor", "ceil", "trunc", "round"] {
+            assert_eq!(show(&format!("{}(2^60 + 1)", function)), "1152921504606846977", "{}", function);
+        }
+        assert_eq!(show("round(2^60 + 1, -1)"), "1152921504606846980");
+        assert_eq!(show("round(-25, -1)"), "-30");
+        assert_eq!(show("round(2^60 + 1, -400)"), "0");
+        // the real versions still take over for other numbers and errors
+        assert_eq!(show("max(2^60, 0.5)"), show("2.0^60"));
+        assert_eq!(eval("mod(2^60, 0)").unwrap_err(), CalcError::DivisionByZero);
+    }
+
+    #[test]
+    fn dividing_by_zero_fails() {
+        for input in ["7 / 0", "7 // 0", "7 % 0", "mod(1, 0)", "rem(1, 0)", "divmod(7, 0)", "7.5 // 0", "7.5 % 0.0"] {
+            assert_eq!(eval(input).unwrap_err(), CalcError::DivisionByZero, "{}", input);
+        }
+        assert_eq!(eval_str_with("3 / 0", &Settings { modulus: Some(BigInt::from(7)), ..Settings::default() }), Err(CalcError::DivisionByZero));
+        assert_eq!(eval_str_with("1 / 0", &Settings { decimal: true, ..Settings::default() }), Err(CalcError::DivisionByZero));
+        // floats divide like IEEE 754
+        assert_eq!(eval_str("1 / 0.0"), Ok(f64::INFINITY));
+        assert_eq!(show("divmod(-7, 2)"), "[-4, 1]");
+    }
+}
@@ -1,218 +1,161 @@
 use std::io;
 
-#[derive(Debug, Clone, Copy)]
-pub enum Token {
-    Number(f64),
-    Operator(char),
-    Empty,
-    End,
-}
-
-impl Token {
-    fn get_number(&self) -> Option<f64> {
-        match self {
-            Token::Number(number) => Some(*number),
-            _ => None,
-        }
+use simple_calc::{Lexer, Token};
+use simple_calc::bigint::BigInt;
+use simple_calc::parser::Parser;
+use simple_calc::eval::{Angle, Context, Factorial, IntegerConversion, Remainder, Settings};
+
+/// Runs a REPL command, the input line with its leading `:` stripped.
+fn run_command(command: &str, settings: &mut Settings) -> Result<(), &'static str> {
+    match command {
+        "deg" => settings.angle = Angle::Degrees,
+        "rad" => settings.angle = Angle::Radians,
+        "mod off" => settings.modulus = None,
+        command if command.starts_with("mod ") => {
+            let modulus = BigInt::parse(command["mod ".len()..].trim()).filter(|modulus| *modulus > BigInt::from(1))
+                .ok_or("the modulus must be an integer greater than 1")?;
+            settings.modulus = Some(modulus);
+        },
+        _ => return Err("unknown command"),
     }
+    Ok(())
 }
 
-struct Lexer {
-    raw: String,
-}
-
-pub trait Scan {
-    fn next(&mut self) -> Token;
+/// Whether an input line starts with a binary operator and continues the
+/// previous result, like `* 2`. Leading signs only do so when followed by
+/// whitespace, so `-5` stays a negative number while `- 5` subtracts.
+fn continues_ans(line: &str) -> bool {
+    line.starts_with(['*', '/', '%', '^', '<', '>', '=', '&', '?', '×', '·', '÷', '≤', '≥', '≠'])
+        || ["+ ", "- ", "+\t", "-\t", "||", "to ", "in "].iter().any(|prefix| line.starts_with(prefix))
 }
 
-impl Lexer {
-    fn new(s: String) -> Self {
-        Lexer {
-            raw: s
+/// Whether the input has unclosed brackets or ends with an operator, so
+/// the expression goes on in the next line.
+fn is_incomplete(input: &str) -> bool {
+    let mut depth = 0;
+    // bracket depths at which the open `|` bars were found
+    let mut bars = Vec::new();
+    let mut last = Token::End;
+    for token in Lexer::new(input) {
+        let mut token = match token {
+            Ok(token) => token.node,
+            Err(_) => return false,
+        };
+        let expects_operand = matches!(last, Token::End | Token::Operator(_))
+            && ! matches!(last, Token::Operator(")" | "]" | "!"));
+        match token {
+            Token::Operator("(" | "[") => depth += 1,
+            Token::Operator(")" | "]") => depth -= 1,
+            Token::Operator("|") if expects_operand => bars.push(depth),
+            Token::Operator("|") if bars.last() == Some(&depth) => {
+                bars.pop();
+                // a closing bar ends an operand just like `)`
+                token = Token::Operator(")");
+            },
+            _ => (),
         }
+        last = token;
     }
+    depth > 0 || ! bars.is_empty() || matches!(last, Token::Operator(operator) if ! matches!(operator, ")" | "]" | "!" | ";"))
 }
 
-impl Scan for Lexer {
-    fn next(&mut self) -> Token {
-        let s = (&self.raw[..]).trim_start();
-        let first = match s.chars().next() {
-            Some(ch) => ch,
-            None => return Token::End,
-        };
-
-        match first {
-            ch if ch.is_ascii_digit() => {
-                let idx = s.find(|c: char| ! c.is_ascii_digit()).unwrap();
-                let number = (&s[..idx]).parse().unwrap();
-                self.raw = (&s[idx..]).to_string();
-                Token::Number(number)
+fn main() {
+    let mut settings = Settings::default();
+    let mut results = 0;
+    let mut verbose = false;
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--verbose" => verbose = true,
+            "--gamma" => settings.factorial = Factorial::Gamma,
+            "--strict" => settings.implicit_mul = false,
+            "--truncate" => settings.integer_conversion = IntegerConversion::Truncate,
+            "--euclid" => settings.remainder = Remainder::Euclidean,
+            "--decimal" => settings.decimal = true,
+            "--symbolic" => settings.symbolic = true,
+            "--money" => {
+                settings.decimal = true;
+                settings.money = Some(2);
             },
-
-            ch if matches!(ch, '+'|'-'|'*'|'/'|'%'|'('|')') => {
-                let operator = s.chars().next().unwrap();
-                self.raw = (&s[1..]).to_string();
-                Token::Operator(operator)
+            arg if arg.starts_with("--money=") => match arg["--money=".len()..].parse() {
+                Ok(places) => {
+                    settings.decimal = true;
+                    settings.money = Some(places);
+                },
+                Err(_) => {
+                    eprintln!("invalid number of decimal places: {}", arg);
+                    std::process::exit(1);
+                },
+            },
+            _ => {
+                eprintln!("unknown option: {}", arg);
+                std::process::exit(1);
             },
-
-            _ => panic!(),
         }
     }
-}
-
-mod parser {
-    use crate::{Token, Scan};
-
-    type Result<T> = std::result::Result<T, &'static str>;
-
-    pub struct Parser<'a, T> {
-        lexer: &'a mut T,
-        current: Token,
-        look_ahead: Token,
-    }
-
-    impl<'a, T: Scan> Parser<'a, T> {
-        pub fn new(lexer: &'a mut T) -> Self {
-            Parser {
-                lexer,
-                current: Token::Empty,
-                look_ahead: Token::Empty,
-            }
-        }
 
-        fn shift(&mut self) -> Token {
-            let result = self.current;
-            self.current = self.look_ahead;
-            self.look_ahead = self.lexer.next();
-            return result;
+    let mut context = Context::new(settings);
+    loop {
+        let mut input = String::new();
+        io::stdin().read_line(&mut input).unwrap();
+        if input.trim().is_empty() {
+            break
         }
 
-        fn eval_primary_expr(&mut self) -> Result<f64> {
-            match self.current {
-                Token::Operator('(') => {
-                    self.shift();
-                    let result = self.eval_add_expr()?;
-
-                    if let Token::Operator(')') = self.look_ahead {
-                        self.current = self.shift();
-                        Ok(result)
-                    } else {
-                        Err("unmatched bracket")
-                    }
-                },
-
-                Token::Number(number) => Ok(number),
-
-                _ => Err("invalid operator"),
+        if let Some(command) = input.trim().strip_prefix(':') {
+            if let Err(msg) = run_command(command, &mut context.settings) {
+                println!("{}", msg);
             }
+            continue;
         }
 
-        fn eval_unary_expr(&mut self) -> Result<f64> {
-            match self.current {
-                Token::Operator('+') | Token::Operator('-') => {
-                    let operator = self.shift();
-                    let oprand = self.eval_primary_expr()?;
-                    let result = match operator {
-                        Token::Operator('+') => oprand,
-                        Token::Operator('-') => - oprand,
-                        _ => unreachable!(),
-                    };
-
-                    self.current = Token::Number(result);
-                    Ok(result)
-                },
-                _ => self.eval_primary_expr(),
+        // a blank line or the end of input gives up on a continuation
+        while is_incomplete(&input) {
+            let mut line = String::new();
+            io::stdin().read_line(&mut line).unwrap();
+            if line.trim().is_empty() {
+                break
             }
+            input.push_str(&line);
         }
 
-        fn eval_mul_expr(&mut self) -> Result<f64> {
-            self.eval_unary_expr()?;
-
-            match self.look_ahead {
-                Token::Operator('*') | Token::Operator('/') => {
-                    let op1 = self.shift().get_number().unwrap();
-
-                    let operator = self.shift();
-                    let op2 = self.eval_unary_expr()?;
-
-                    let result = match operator {
-                        Token::Operator('*') => op1 * op2,
-                        Token::Operator('/') => op1 / op2,
-                        _ => unreachable!(),
-                    };
-
-                    self.current = Token::Number(result);
-
-                    self.eval_mul_expr()
-                },
+        let input = if continues_ans(input.trim_start()) && context.variables.contains_key("ans") {
+            format!("ans {}", input)
+        } else {
+            input
+        };
 
-                _ => if let Token::Number(result) = self.current {
-                    Ok(result)
-                } else {
-                    Err("error occurred")
+        let mut lexer = Lexer::new(&input);
+        let mut parser = Parser::new(&mut lexer, &context.settings);
+        let statements = match parser.parse() {
+            Ok(statements) => statements,
+            Err(diagnostics) => {
+                for diagnostic in diagnostics {
+                    println!("{}", diagnostic.render(input.trim_end()));
                 }
-            }
-        }
-
-        fn eval_add_expr(&mut self) -> Result<f64> {
-            self.eval_mul_expr()?;
-
-            match self.look_ahead {
-                Token::Operator('+') | Token::Operator('-') => {
-                    let op1 = self.shift().get_number().unwrap();
-
-                    let operator = self.shift();
-                    let op2 = self.eval_mul_expr()?;
-
-                    let result = match operator {
-                        Token::Operator('+') => op1 + op2,
-                        Token::Operator('-') => op1 - op2,
-                        _ => unreachable!(),
-                    };
-
-                    self.current = Token::Number(result);
+                continue;
+            },
+        };
 
-                    self.eval_add_expr()
+        // only the value of the last statement is printed, unless verbose
+        for (i, statement) in statements.iter().enumerate() {
+            match context.exec(statement) {
+                Ok(Some(result)) => {
+                    context.variables.insert("ans".to_string(), result.clone());
+                    if verbose || i + 1 == statements.len() {
+                        match context.settings.money {
+                            Some(places) => println!("{:.*}", places as usize, result),
+                            None => println!("{}", result),
+                        }
+                        results += 1;
+                        context.variables.insert(format!("_{}", results), result);
+                    }
+                },
+                Ok(None) => (),
+                Err(msg) => {
+                    println!("{}", msg);
+                    break;
                 },
-
-                _ => if let Token::Number(result) = self.current {
-                    Ok(result)
-                } else {
-                    Err("error occurred")
-                }
-            }
-        }
-
-        pub fn eval(&mut self) -> Result<f64> {
-            self.shift();
-            self.shift();
-            let result = self.eval_add_expr();
-            if let Token::End = self.look_ahead {
-                result
-            } else {
-                Err("invalid expression")
             }
         }
     }
 }
-
-use parser::Parser;
-
-fn main() {
-    loop {
-        let mut input = String::new();
-        io::stdin().read_line(&mut input).unwrap();
-        if input.trim().is_empty() {
-            break
-        }
-
-        let mut lexer = Lexer::new(input);
-        let mut parser = Parser::new(&mut lexer);
-        let result = parser.eval();
-
-        match result {
-            Ok(result) => println!("{}", result),
-            Err(msg) => println!("{}", msg),
-        }
-    }
-}